@@ -0,0 +1,5 @@
+CREATE TABLE neighborhood (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    delivery_fee DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (delivery_fee >= 0)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE address (
+    id SERIAL PRIMARY KEY,
+    street VARCHAR NOT NULL,
+    number VARCHAR NOT NULL,
+    neighborhood_id INTEGER NOT NULL REFERENCES neighborhood (id),
+    complement VARCHAR,
+    observation TEXT,
+    delivery_fee DOUBLE PRECISION NOT NULL CHECK (delivery_fee >= 0)
+);
+
+CREATE INDEX address_neighborhood_id_idx ON address (neighborhood_id);
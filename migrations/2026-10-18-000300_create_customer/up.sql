@@ -0,0 +1,8 @@
+CREATE TABLE customer (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    phone VARCHAR,
+    address_id INTEGER NOT NULL REFERENCES address (id)
+);
+
+CREATE INDEX customer_address_id_idx ON customer (address_id);
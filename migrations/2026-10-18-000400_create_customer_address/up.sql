@@ -0,0 +1,5 @@
+CREATE TABLE customer_address (
+    customer_id INTEGER NOT NULL REFERENCES customer (id) ON DELETE CASCADE,
+    address_id INTEGER NOT NULL REFERENCES address (id) ON DELETE CASCADE,
+    PRIMARY KEY (customer_id, address_id)
+);
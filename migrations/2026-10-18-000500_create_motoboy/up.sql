@@ -0,0 +1,7 @@
+CREATE TABLE motoboy (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    phone VARCHAR NOT NULL,
+    daily_salary DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (daily_salary >= 0),
+    is_active BOOLEAN NOT NULL DEFAULT TRUE
+);
@@ -0,0 +1,7 @@
+CREATE TABLE item (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    price DOUBLE PRECISION NOT NULL CHECK (price >= 0),
+    description TEXT NOT NULL DEFAULT '',
+    is_active BOOLEAN NOT NULL DEFAULT TRUE
+);
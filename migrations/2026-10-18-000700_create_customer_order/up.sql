@@ -0,0 +1,16 @@
+CREATE TABLE customer_order (
+    id SERIAL PRIMARY KEY,
+    date DATE NOT NULL DEFAULT CURRENT_DATE,
+    customer_id INTEGER NOT NULL REFERENCES customer (id),
+    motoboy_id INTEGER REFERENCES motoboy (id),
+    address_id INTEGER REFERENCES address (id),
+    source SMALLINT NOT NULL DEFAULT 0,
+    additional DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (additional >= 0),
+    delivery_fee DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (delivery_fee >= 0),
+    discount DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (discount >= 0),
+    status SMALLINT NOT NULL DEFAULT 0
+);
+
+CREATE INDEX customer_order_date_idx ON customer_order (date);
+CREATE INDEX customer_order_customer_id_idx ON customer_order (customer_id);
+CREATE INDEX customer_order_motoboy_id_idx ON customer_order (motoboy_id);
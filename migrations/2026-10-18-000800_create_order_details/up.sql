@@ -0,0 +1,10 @@
+CREATE TABLE order_details (
+    order_id INTEGER NOT NULL REFERENCES customer_order (id) ON DELETE CASCADE,
+    item_id INTEGER NOT NULL REFERENCES item (id),
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    unit_price DOUBLE PRECISION NOT NULL CHECK (unit_price >= 0),
+    total_price DOUBLE PRECISION NOT NULL GENERATED ALWAYS AS (quantity * unit_price) STORED,
+    PRIMARY KEY (order_id, item_id)
+);
+
+CREATE INDEX order_details_item_id_idx ON order_details (item_id);
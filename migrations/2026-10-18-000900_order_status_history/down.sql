@@ -0,0 +1,3 @@
+DROP TABLE order_status_history;
+
+ALTER TABLE customer_order DROP CONSTRAINT customer_order_status_check;
@@ -0,0 +1,15 @@
+-- status values follow OrderStatus: 0 received, 1 preparing, 2 ready,
+-- 3 out_for_delivery, 4 delivered, 5 cancelled
+ALTER TABLE customer_order
+    ADD CONSTRAINT customer_order_status_check CHECK (status BETWEEN 0 AND 5);
+
+CREATE TABLE order_status_history (
+    id SERIAL PRIMARY KEY,
+    order_id INTEGER NOT NULL REFERENCES customer_order (id) ON DELETE CASCADE,
+    from_status SMALLINT NOT NULL CHECK (from_status BETWEEN 0 AND 5),
+    to_status SMALLINT NOT NULL CHECK (to_status BETWEEN 0 AND 5),
+    changed_by VARCHAR NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX order_status_history_order_id_idx ON order_status_history (order_id);
@@ -0,0 +1,8 @@
+ALTER TABLE customer_order ADD COLUMN source SMALLINT NOT NULL DEFAULT 0;
+
+UPDATE customer_order
+SET source = CASE WHEN sales_channel_id BETWEEN 1 AND 4 THEN sales_channel_id - 1 ELSE 0 END;
+
+ALTER TABLE customer_order DROP COLUMN sales_channel_id;
+
+DROP TABLE sales_channel;
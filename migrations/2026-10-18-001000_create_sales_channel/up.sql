@@ -0,0 +1,28 @@
+CREATE TABLE sales_channel (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL UNIQUE,
+    commission_percent DOUBLE PRECISION NOT NULL DEFAULT 0
+        CHECK (commission_percent >= 0 AND commission_percent <= 100),
+    is_active BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+-- ids 1..4 line up with the old `source` values 0..3
+INSERT INTO sales_channel (id, name, commission_percent) VALUES
+    (1, 'Counter', 0),
+    (2, 'Phone', 0),
+    (3, 'WhatsApp', 0),
+    (4, 'Website', 0),
+    (5, 'iFood', 12);
+
+SELECT setval('sales_channel_id_seq', (SELECT max(id) FROM sales_channel));
+
+ALTER TABLE customer_order ADD COLUMN sales_channel_id INTEGER REFERENCES sales_channel (id);
+
+UPDATE customer_order
+SET sales_channel_id = CASE WHEN source BETWEEN 0 AND 3 THEN source + 1 ELSE 1 END;
+
+ALTER TABLE customer_order
+    ALTER COLUMN sales_channel_id SET NOT NULL,
+    DROP COLUMN source;
+
+CREATE INDEX customer_order_sales_channel_id_idx ON customer_order (sales_channel_id);
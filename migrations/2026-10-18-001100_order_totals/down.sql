@@ -0,0 +1,6 @@
+DROP TRIGGER order_details_refresh_subtotal ON order_details;
+DROP FUNCTION refresh_order_subtotal();
+
+ALTER TABLE customer_order
+    DROP COLUMN grand_total,
+    DROP COLUMN subtotal;
@@ -0,0 +1,32 @@
+ALTER TABLE customer_order
+    ADD COLUMN subtotal DOUBLE PRECISION NOT NULL DEFAULT 0,
+    ADD COLUMN grand_total DOUBLE PRECISION NOT NULL
+        GENERATED ALWAYS AS (subtotal + additional + delivery_fee - discount) STORED;
+
+-- subtotal is the sum of the order's lines and only ever written by this trigger
+CREATE FUNCTION refresh_order_subtotal() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        UPDATE customer_order
+        SET subtotal = COALESCE((SELECT sum(total_price) FROM order_details WHERE order_id = OLD.order_id), 0)
+        WHERE id = OLD.order_id;
+    END IF;
+
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        UPDATE customer_order
+        SET subtotal = COALESCE((SELECT sum(total_price) FROM order_details WHERE order_id = NEW.order_id), 0)
+        WHERE id = NEW.order_id;
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER order_details_refresh_subtotal
+    AFTER INSERT OR UPDATE OR DELETE ON order_details
+    FOR EACH ROW EXECUTE FUNCTION refresh_order_subtotal();
+
+UPDATE customer_order o
+SET subtotal = d.subtotal
+FROM (SELECT order_id, sum(total_price) AS subtotal FROM order_details GROUP BY order_id) d
+WHERE d.order_id = o.id;
@@ -0,0 +1,5 @@
+ALTER TABLE order_details
+    DROP CONSTRAINT order_details_order_id_item_id_key,
+    DROP COLUMN id;
+
+ALTER TABLE order_details ADD PRIMARY KEY (order_id, item_id);
@@ -0,0 +1,5 @@
+ALTER TABLE order_details DROP CONSTRAINT order_details_pkey;
+
+ALTER TABLE order_details
+    ADD COLUMN id SERIAL PRIMARY KEY,
+    ADD CONSTRAINT order_details_order_id_item_id_key UNIQUE (order_id, item_id);
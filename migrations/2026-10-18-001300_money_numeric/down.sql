@@ -0,0 +1,21 @@
+ALTER TABLE order_details DROP COLUMN total_price;
+ALTER TABLE customer_order DROP COLUMN grand_total;
+
+ALTER TABLE neighborhood ALTER COLUMN delivery_fee TYPE DOUBLE PRECISION;
+ALTER TABLE address ALTER COLUMN delivery_fee TYPE DOUBLE PRECISION;
+ALTER TABLE motoboy ALTER COLUMN daily_salary TYPE DOUBLE PRECISION;
+ALTER TABLE item ALTER COLUMN price TYPE DOUBLE PRECISION;
+ALTER TABLE order_details ALTER COLUMN unit_price TYPE DOUBLE PRECISION;
+
+ALTER TABLE customer_order
+    ALTER COLUMN additional TYPE DOUBLE PRECISION,
+    ALTER COLUMN delivery_fee TYPE DOUBLE PRECISION,
+    ALTER COLUMN discount TYPE DOUBLE PRECISION,
+    ALTER COLUMN subtotal TYPE DOUBLE PRECISION;
+
+ALTER TABLE order_details
+    ADD COLUMN total_price DOUBLE PRECISION NOT NULL GENERATED ALWAYS AS (quantity * unit_price) STORED;
+
+ALTER TABLE customer_order
+    ADD COLUMN grand_total DOUBLE PRECISION NOT NULL
+        GENERATED ALWAYS AS (subtotal + additional + delivery_fee - discount) STORED;
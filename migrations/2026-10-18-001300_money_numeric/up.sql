@@ -0,0 +1,26 @@
+-- generated columns have to go before the columns they are computed from can change type
+ALTER TABLE order_details DROP COLUMN total_price;
+ALTER TABLE customer_order DROP COLUMN grand_total;
+
+ALTER TABLE neighborhood ALTER COLUMN delivery_fee TYPE NUMERIC(12, 2) USING round(delivery_fee::numeric, 2);
+ALTER TABLE address ALTER COLUMN delivery_fee TYPE NUMERIC(12, 2) USING round(delivery_fee::numeric, 2);
+ALTER TABLE motoboy ALTER COLUMN daily_salary TYPE NUMERIC(12, 2) USING round(daily_salary::numeric, 2);
+ALTER TABLE item ALTER COLUMN price TYPE NUMERIC(12, 2) USING round(price::numeric, 2);
+ALTER TABLE order_details ALTER COLUMN unit_price TYPE NUMERIC(12, 2) USING round(unit_price::numeric, 2);
+
+ALTER TABLE customer_order
+    ALTER COLUMN additional TYPE NUMERIC(12, 2) USING round(additional::numeric, 2),
+    ALTER COLUMN delivery_fee TYPE NUMERIC(12, 2) USING round(delivery_fee::numeric, 2),
+    ALTER COLUMN discount TYPE NUMERIC(12, 2) USING round(discount::numeric, 2),
+    ALTER COLUMN subtotal TYPE NUMERIC(12, 2) USING round(subtotal::numeric, 2);
+
+ALTER TABLE order_details
+    ADD COLUMN total_price NUMERIC(12, 2) NOT NULL GENERATED ALWAYS AS (quantity * unit_price) STORED;
+
+ALTER TABLE customer_order
+    ADD COLUMN grand_total NUMERIC(12, 2) NOT NULL
+        GENERATED ALWAYS AS (subtotal + additional + delivery_fee - discount) STORED;
+
+-- unit prices were rounded, so the line sums may have moved
+UPDATE customer_order o
+SET subtotal = COALESCE((SELECT sum(total_price) FROM order_details WHERE order_id = o.id), 0);
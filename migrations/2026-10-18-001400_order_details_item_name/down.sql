@@ -0,0 +1 @@
+ALTER TABLE order_details DROP COLUMN item_name;
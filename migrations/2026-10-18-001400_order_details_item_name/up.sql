@@ -0,0 +1,9 @@
+-- name of the item when the line was ordered, so receipts survive menu edits
+ALTER TABLE order_details ADD COLUMN item_name VARCHAR;
+
+UPDATE order_details d
+SET item_name = i.name
+FROM item i
+WHERE i.id = d.item_id;
+
+ALTER TABLE order_details ALTER COLUMN item_name SET NOT NULL;
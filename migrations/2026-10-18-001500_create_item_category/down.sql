@@ -0,0 +1,3 @@
+ALTER TABLE item DROP COLUMN category_id;
+
+DROP TABLE item_category;
@@ -0,0 +1,9 @@
+CREATE TABLE item_category (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL UNIQUE,
+    display_order INTEGER NOT NULL DEFAULT 0
+);
+
+ALTER TABLE item ADD COLUMN category_id INTEGER REFERENCES item_category (id) ON DELETE SET NULL;
+
+CREATE INDEX item_category_id_idx ON item (category_id);
@@ -0,0 +1,9 @@
+ALTER TABLE order_details
+    DROP CONSTRAINT order_details_order_id_item_id_options_key_key,
+    DROP COLUMN options_key,
+    ADD CONSTRAINT order_details_order_id_item_id_key UNIQUE (order_id, item_id);
+
+DROP TABLE order_line_modifier;
+DROP TABLE item_modifier_group;
+DROP TABLE modifier_option;
+DROP TABLE modifier_group;
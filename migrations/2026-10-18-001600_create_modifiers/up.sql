@@ -0,0 +1,44 @@
+CREATE TABLE modifier_group (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    min_selections INTEGER NOT NULL DEFAULT 0 CHECK (min_selections >= 0),
+    max_selections INTEGER NOT NULL DEFAULT 1 CHECK (max_selections >= 1),
+    CHECK (min_selections <= max_selections)
+);
+
+CREATE TABLE modifier_option (
+    id SERIAL PRIMARY KEY,
+    group_id INTEGER NOT NULL REFERENCES modifier_group (id) ON DELETE CASCADE,
+    name VARCHAR NOT NULL,
+    price_delta NUMERIC(12, 2) NOT NULL DEFAULT 0,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE INDEX modifier_option_group_id_idx ON modifier_option (group_id);
+
+CREATE TABLE item_modifier_group (
+    item_id INTEGER NOT NULL REFERENCES item (id) ON DELETE CASCADE,
+    group_id INTEGER NOT NULL REFERENCES modifier_group (id) ON DELETE CASCADE,
+    display_order INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (item_id, group_id)
+);
+
+CREATE INDEX item_modifier_group_group_id_idx ON item_modifier_group (group_id);
+
+-- options chosen on an order line, with names and prices as they were at order time
+CREATE TABLE order_line_modifier (
+    id SERIAL PRIMARY KEY,
+    order_details_id INTEGER NOT NULL REFERENCES order_details (id) ON DELETE CASCADE,
+    modifier_option_id INTEGER REFERENCES modifier_option (id) ON DELETE SET NULL,
+    group_name VARCHAR NOT NULL,
+    option_name VARCHAR NOT NULL,
+    price_delta NUMERIC(12, 2) NOT NULL
+);
+
+CREATE INDEX order_line_modifier_order_details_id_idx ON order_line_modifier (order_details_id);
+
+-- sorted option ids of the line, so the same item with other options gets its own line
+ALTER TABLE order_details
+    ADD COLUMN options_key VARCHAR NOT NULL DEFAULT '',
+    DROP CONSTRAINT order_details_order_id_item_id_key,
+    ADD CONSTRAINT order_details_order_id_item_id_options_key_key UNIQUE (order_id, item_id, options_key);
@@ -0,0 +1,3 @@
+DROP TABLE order_line_component;
+DROP TABLE combo_component_swap;
+DROP TABLE combo_component;
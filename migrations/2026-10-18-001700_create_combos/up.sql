@@ -0,0 +1,34 @@
+-- an item with components is sold as a combo at its own price
+CREATE TABLE combo_component (
+    id SERIAL PRIMARY KEY,
+    combo_item_id INTEGER NOT NULL REFERENCES item (id) ON DELETE CASCADE,
+    item_id INTEGER NOT NULL REFERENCES item (id),
+    quantity INTEGER NOT NULL DEFAULT 1 CHECK (quantity > 0),
+    display_order INTEGER NOT NULL DEFAULT 0,
+    CHECK (combo_item_id <> item_id)
+);
+
+CREATE INDEX combo_component_combo_item_id_idx ON combo_component (combo_item_id);
+CREATE INDEX combo_component_item_id_idx ON combo_component (item_id);
+
+-- items a component may be swapped for, e.g. juice instead of soda for 2.00 more
+CREATE TABLE combo_component_swap (
+    component_id INTEGER NOT NULL REFERENCES combo_component (id) ON DELETE CASCADE,
+    item_id INTEGER NOT NULL REFERENCES item (id) ON DELETE CASCADE,
+    price_delta NUMERIC(12, 2) NOT NULL DEFAULT 0,
+    PRIMARY KEY (component_id, item_id)
+);
+
+-- what went to the kitchen for one unit of a combo line
+CREATE TABLE order_line_component (
+    id SERIAL PRIMARY KEY,
+    order_details_id INTEGER NOT NULL REFERENCES order_details (id) ON DELETE CASCADE,
+    component_id INTEGER REFERENCES combo_component (id) ON DELETE SET NULL,
+    item_id INTEGER NOT NULL REFERENCES item (id),
+    item_name VARCHAR NOT NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    unit_price NUMERIC(12, 2) NOT NULL
+);
+
+CREATE INDEX order_line_component_order_details_id_idx ON order_line_component (order_details_id);
+CREATE INDEX order_line_component_item_id_idx ON order_line_component (item_id);
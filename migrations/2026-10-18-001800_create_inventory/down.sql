@@ -0,0 +1,4 @@
+DROP TABLE stock_movement;
+DROP FUNCTION apply_stock_movement();
+DROP TABLE recipe_ingredient;
+DROP TABLE ingredient;
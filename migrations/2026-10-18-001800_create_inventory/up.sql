@@ -0,0 +1,46 @@
+CREATE TABLE ingredient (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL UNIQUE,
+    unit VARCHAR NOT NULL,
+    -- only ever written by the stock_movement trigger, may go negative when the
+    -- kitchen uses more than was recorded
+    stock NUMERIC(12, 3) NOT NULL DEFAULT 0,
+    reorder_level NUMERIC(12, 3) NOT NULL DEFAULT 0 CHECK (reorder_level >= 0)
+);
+
+-- how much of each ingredient one unit of an item uses
+CREATE TABLE recipe_ingredient (
+    item_id INTEGER NOT NULL REFERENCES item (id) ON DELETE CASCADE,
+    ingredient_id INTEGER NOT NULL REFERENCES ingredient (id),
+    quantity NUMERIC(12, 3) NOT NULL CHECK (quantity > 0),
+    PRIMARY KEY (item_id, ingredient_id)
+);
+
+CREATE INDEX recipe_ingredient_ingredient_id_idx ON recipe_ingredient (ingredient_id);
+
+-- kind values follow StockMovementKind: 0 purchase, 1 adjustment, 2 waste,
+-- 3 consumption, 4 cancellation. quantity is signed, negative takes stock out.
+CREATE TABLE stock_movement (
+    id SERIAL PRIMARY KEY,
+    ingredient_id INTEGER NOT NULL REFERENCES ingredient (id) ON DELETE CASCADE,
+    kind SMALLINT NOT NULL CHECK (kind BETWEEN 0 AND 4),
+    quantity NUMERIC(12, 3) NOT NULL CHECK (quantity <> 0),
+    order_id INTEGER REFERENCES customer_order (id) ON DELETE SET NULL,
+    note VARCHAR,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX stock_movement_ingredient_id_idx ON stock_movement (ingredient_id);
+CREATE INDEX stock_movement_order_id_idx ON stock_movement (order_id);
+
+CREATE FUNCTION apply_stock_movement() RETURNS trigger AS $$
+BEGIN
+    UPDATE ingredient SET stock = stock + NEW.quantity WHERE id = NEW.ingredient_id;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER stock_movement_apply
+    AFTER INSERT ON stock_movement
+    FOR EACH ROW EXECUTE FUNCTION apply_stock_movement();
@@ -0,0 +1 @@
+DROP TABLE availability_window;
@@ -0,0 +1,16 @@
+-- when an item, or every item of a category, is served. weekday is ISO, 1 is
+-- Monday and 7 Sunday, times are in the store timezone. A window ending before
+-- its start runs past midnight into the next day.
+CREATE TABLE availability_window (
+    id SERIAL PRIMARY KEY,
+    item_id INTEGER REFERENCES item (id) ON DELETE CASCADE,
+    category_id INTEGER REFERENCES item_category (id) ON DELETE CASCADE,
+    weekday SMALLINT NOT NULL CHECK (weekday BETWEEN 1 AND 7),
+    start_time TIME NOT NULL,
+    end_time TIME NOT NULL,
+    CHECK ((item_id IS NULL) <> (category_id IS NULL)),
+    CHECK (start_time <> end_time)
+);
+
+CREATE INDEX availability_window_item_id_idx ON availability_window (item_id);
+CREATE INDEX availability_window_category_id_idx ON availability_window (category_id);
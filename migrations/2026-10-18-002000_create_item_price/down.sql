@@ -0,0 +1,3 @@
+DROP TRIGGER item_record_price ON item;
+DROP FUNCTION record_item_price();
+DROP TABLE item_price;
@@ -0,0 +1,34 @@
+-- every price an item has had or is scheduled to have. item.price stays the
+-- price in effect now: changing it records a row here, and due scheduled rows
+-- are copied back into it.
+CREATE TABLE item_price (
+    id SERIAL PRIMARY KEY,
+    item_id INTEGER NOT NULL REFERENCES item (id) ON DELETE CASCADE,
+    price NUMERIC(12, 2) NOT NULL,
+    effective_from TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (item_id, effective_from)
+);
+
+CREATE FUNCTION record_item_price() RETURNS trigger AS $$
+BEGIN
+    IF (SELECT price FROM item_price
+        WHERE item_id = NEW.id AND effective_from <= now()
+        ORDER BY effective_from DESC
+        LIMIT 1) IS DISTINCT FROM NEW.price
+    THEN
+        INSERT INTO item_price (item_id, price, effective_from)
+        VALUES (NEW.id, NEW.price, now())
+        ON CONFLICT (item_id, effective_from) DO UPDATE SET price = EXCLUDED.price;
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER item_record_price
+    AFTER INSERT OR UPDATE OF price ON item
+    FOR EACH ROW EXECUTE FUNCTION record_item_price();
+
+INSERT INTO item_price (item_id, price, effective_from)
+SELECT id, price, now() FROM item;
@@ -0,0 +1,2 @@
+DROP TRIGGER customer_link_default_address ON customer;
+DROP FUNCTION link_default_address();
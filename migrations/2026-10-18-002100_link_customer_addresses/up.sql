@@ -0,0 +1,19 @@
+-- customer.address_id is the customer's default address and is always one of
+-- their customer_address rows.
+INSERT INTO customer_address (customer_id, address_id)
+SELECT id, address_id FROM customer
+ON CONFLICT DO NOTHING;
+
+CREATE FUNCTION link_default_address() RETURNS trigger AS $$
+BEGIN
+    INSERT INTO customer_address (customer_id, address_id)
+    VALUES (NEW.id, NEW.address_id)
+    ON CONFLICT DO NOTHING;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER customer_link_default_address
+    AFTER INSERT OR UPDATE OF address_id ON customer
+    FOR EACH ROW EXECUTE FUNCTION link_default_address();
@@ -0,0 +1,2 @@
+DROP INDEX customer_phone_key;
+ALTER TABLE customer DROP COLUMN phone_duplicate;
@@ -0,0 +1,11 @@
+-- phones are unique once normalized. Customers that already shared one keep
+-- it, flagged, until they are merged or their phone is corrected; the lookup
+-- by phone only sees the unflagged one.
+ALTER TABLE customer ADD COLUMN phone_duplicate BOOLEAN NOT NULL DEFAULT false;
+
+UPDATE customer SET phone = NULL WHERE btrim(phone) = '';
+
+UPDATE customer c SET phone_duplicate = true
+WHERE EXISTS (SELECT 1 FROM customer o WHERE o.phone = c.phone AND o.id < c.id);
+
+CREATE UNIQUE INDEX customer_phone_key ON customer (phone) WHERE NOT phone_duplicate;
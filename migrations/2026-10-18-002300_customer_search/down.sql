@@ -0,0 +1,4 @@
+DROP INDEX neighborhood_name_trgm;
+DROP INDEX address_street_trgm;
+DROP INDEX customer_phone_trgm;
+DROP INDEX customer_name_trgm;
@@ -0,0 +1,7 @@
+-- trigram matching for GET /customer/search, tolerant of misspelled names
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX customer_name_trgm ON customer USING gin (name gin_trgm_ops);
+CREATE INDEX customer_phone_trgm ON customer USING gin (phone gin_trgm_ops);
+CREATE INDEX address_street_trgm ON address USING gin (street gin_trgm_ops);
+CREATE INDEX neighborhood_name_trgm ON neighborhood USING gin (name gin_trgm_ops);
@@ -0,0 +1,15 @@
+-- audit trail of merged duplicate customers. The merged customer is gone, so
+-- what it was and what moved off it is kept here.
+CREATE TABLE customer_merge (
+    id SERIAL PRIMARY KEY,
+    customer_id INTEGER REFERENCES customer (id) ON DELETE SET NULL,
+    merged_customer_id INTEGER NOT NULL,
+    merged_name VARCHAR NOT NULL,
+    merged_phone VARCHAR,
+    merged_address_id INTEGER NOT NULL,
+    order_ids INTEGER[] NOT NULL,
+    address_ids INTEGER[] NOT NULL,
+    merged_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX customer_merge_customer_id ON customer_merge (customer_id);
@@ -0,0 +1,10 @@
+DROP TRIGGER customer_order_snapshot_commission ON customer_order;
+DROP FUNCTION snapshot_commission_percent();
+
+ALTER TABLE customer_order DROP COLUMN commission_percent;
+
+ALTER TABLE sales_channel DROP CONSTRAINT sales_channel_commission_percent_check;
+
+ALTER TABLE sales_channel
+    ALTER COLUMN commission_percent TYPE DOUBLE PRECISION,
+    ADD CONSTRAINT sales_channel_commission_percent_check CHECK (commission_percent >= 0 AND commission_percent <= 100);
@@ -0,0 +1,35 @@
+-- commission rates are exact decimals, and every order keeps the rate of its
+-- channel at the time it was taken so editing a channel leaves past revenue alone
+ALTER TABLE sales_channel DROP CONSTRAINT sales_channel_commission_percent_check;
+
+ALTER TABLE sales_channel
+    ALTER COLUMN commission_percent TYPE NUMERIC(5, 2) USING round(commission_percent::numeric, 2),
+    ADD CONSTRAINT sales_channel_commission_percent_check CHECK (commission_percent BETWEEN 0 AND 100);
+
+ALTER TABLE customer_order ADD COLUMN commission_percent NUMERIC(5, 2);
+
+UPDATE customer_order o
+SET commission_percent = c.commission_percent
+FROM sales_channel c
+WHERE c.id = o.sales_channel_id;
+
+ALTER TABLE customer_order
+    ALTER COLUMN commission_percent SET NOT NULL,
+    ADD CONSTRAINT customer_order_commission_percent_check CHECK (commission_percent BETWEEN 0 AND 100);
+
+CREATE FUNCTION snapshot_commission_percent() RETURNS trigger AS $$
+BEGIN
+    -- a PUT rewriting the same channel keeps the rate the order was taken at
+    IF TG_OP = 'INSERT' OR NEW.sales_channel_id IS DISTINCT FROM OLD.sales_channel_id THEN
+        SELECT commission_percent INTO NEW.commission_percent
+        FROM sales_channel
+        WHERE id = NEW.sales_channel_id;
+    END IF;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER customer_order_snapshot_commission
+    BEFORE INSERT OR UPDATE OF sales_channel_id ON customer_order
+    FOR EACH ROW EXECUTE FUNCTION snapshot_commission_percent();
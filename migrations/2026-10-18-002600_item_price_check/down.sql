@@ -0,0 +1 @@
+ALTER TABLE item_price DROP CONSTRAINT item_price_price_check;
@@ -0,0 +1,3 @@
+-- item.price has always been non-negative, scheduled prices must be too.
+ALTER TABLE item_price
+    ADD CONSTRAINT item_price_price_check CHECK (price >= 0);
@@ -0,0 +1,116 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use rocket::figment::providers::Env;
+use rocket::figment::{self, Figment};
+use serde::Deserialize;
+
+/// Application settings, read from `Rocket.toml`, `.env` and the environment.
+///
+/// Everything lives in the same figment Rocket uses for its own `address`/`port`,
+/// so profiles (`[debug]`, `[release]`, `[staging]`, ...) apply to both.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AppConfig {
+    pub database: DatabaseConfig,
+    pub store: StoreConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DatabaseConfig {
+    pub url: String,
+    #[serde(default = "default_pool_size")]
+    pub pool_size: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoreConfig {
+    pub name: String,
+    pub timezone: Tz,
+    pub currency: String,
+    #[serde(default)]
+    pub phone: PhoneConfig,
+}
+
+/// Defaults filled into phone numbers typed without them, see `phone::normalize`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PhoneConfig {
+    pub country_code: String,
+    pub area_code: Option<String>,
+    pub area_code_digits: usize,
+    pub min_local_digits: usize,
+    pub max_local_digits: usize,
+}
+
+impl Default for PhoneConfig {
+    fn default() -> PhoneConfig {
+        PhoneConfig {
+            country_code: "55".to_string(),
+            area_code: None,
+            area_code_digits: 2,
+            min_local_digits: 8,
+            max_local_digits: 9,
+        }
+    }
+}
+
+impl StoreConfig {
+    /// The current time on the store's wall clock.
+    pub fn now(&self) -> DateTime<Tz> {
+        Utc::now().with_timezone(&self.timezone)
+    }
+}
+
+fn default_pool_size() -> u32 {
+    10
+}
+
+/// Rocket's figment plus a plain `DATABASE_URL`, with `.env` loaded first.
+/// Like `ROCKET_*` variables it is global, so it wins over every profile's
+/// `Rocket.toml` values.
+pub fn figment() -> Figment {
+    dotenv::dotenv().ok();
+
+    rocket::Config::figment()
+        .merge(Env::raw().only(&["DATABASE_URL"]).map(|_| "database.url".into()).global())
+}
+
+impl AppConfig {
+    pub fn from_figment(figment: &Figment) -> Result<AppConfig, Box<figment::Error>> {
+        let config = figment.extract::<AppConfig>()?;
+        config.validate()
+            .map_err(|message| Box::new(figment::Error::from(message)))?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.database.url.trim().is_empty() {
+            return Err("database.url must not be empty".to_string());
+        }
+
+        if self.database.pool_size == 0 {
+            return Err("database.pool_size must be at least 1".to_string());
+        }
+
+        let currency = &self.store.currency;
+        if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(format!("store.currency must be an ISO 4217 code such as \"BRL\", found \"{}\"", currency));
+        }
+
+        let phone = &self.store.phone;
+        let all_digits = |value: &str| !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit());
+        if !all_digits(&phone.country_code) || phone.country_code.len() > 3 {
+            return Err(format!("store.phone.country_code must be 1 to 3 digits, found \"{}\"", phone.country_code));
+        }
+        if let Some(area_code) = &phone.area_code {
+            if !all_digits(area_code) || area_code.len() != phone.area_code_digits {
+                return Err(format!("store.phone.area_code must be {} digits, found \"{}\"", phone.area_code_digits, area_code));
+            }
+        }
+        if phone.min_local_digits == 0 || phone.min_local_digits > phone.max_local_digits {
+            return Err("store.phone.min_local_digits must be between 1 and max_local_digits".to_string());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::State;
+
+use crate::config::DatabaseConfig;
+
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// Builds the shared Postgres pool. Connections are opened lazily, so a database
+/// outage after startup turns into 503s instead of taking the worker down.
+pub fn init_pool(config: &DatabaseConfig) -> DbPool {
+    let manager = ConnectionManager::<PgConnection>::new(&config.url);
+
+    Pool::builder()
+        .max_size(config.pool_size)
+        .connection_timeout(Duration::from_secs(5))
+        .build_unchecked(manager)
+}
+
+/// Applies every pending migration, returning the versions that were run.
+pub fn run_migrations(pool: &DbPool) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+
+    let versions = conn.run_pending_migrations(MIGRATIONS)?
+        .iter()
+        .map(|version| version.to_string())
+        .collect();
+
+    Ok(versions)
+}
+
+/// Request guard handing out a pooled connection.
+///
+/// Diesel is blocking and the handlers using this are plain `fn`s, so their
+/// queries run on Rocket's async worker threads and hold one for as long as
+/// they take. Queries here are short, but a slow one stalls every request
+/// sharing that worker: keep `workers` at least as large as the pool, and move
+/// anything long running to `spawn_blocking` like the startup and background
+/// jobs in `mount` do.
+pub struct DbConn(PooledConnection<ConnectionManager<PgConnection>>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DbConn {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let pool = match request.guard::<&State<DbPool>>().await {
+            Outcome::Success(pool) => pool.inner().clone(),
+            _ => return Outcome::Error((Status::InternalServerError, ())),
+        };
+
+        // Pool::get blocks for up to connection_timeout, keep it off the async workers.
+        match rocket::tokio::task::spawn_blocking(move || pool.get()).await {
+            Ok(Ok(conn)) => Outcome::Success(DbConn(conn)),
+            _ => Outcome::Error((Status::ServiceUnavailable, ())),
+        }
+    }
+}
+
+impl Deref for DbConn {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for DbConn {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
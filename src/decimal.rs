@@ -0,0 +1,145 @@
+/// Declares a newtype over `Decimal` stored in a `NUMERIC(precision, scale)`
+/// column, such as `Money`:
+///
+/// ```ignore
+/// decimal_type! {
+///     /// An amount in the store currency, exact to the cent.
+///     pub struct Money: NUMERIC(12, 2);
+///     noun = "amount", expecting = "an amount such as \"12.50\" or 12.5";
+/// }
+/// ```
+///
+/// The type reads and writes the column, is serialized as a string with
+/// `scale` decimals so it survives JSON parsers that read numbers as floats,
+/// and is parsed from a string, a number or a form field with `parse`.
+///
+/// `parse` rejects more decimals than the column keeps and anything the column
+/// can't hold, or anything outside `range = min..=max` when one is given, so
+/// client input fails validation instead of the insert.
+macro_rules! decimal_type {
+    (@range $precision:literal, $scale:literal) => {{
+        let max = ::rust_decimal::Decimal::from_i128_with_scale(10_i128.pow($precision) - 1, $scale);
+        (-max, max)
+    }};
+    (@range $precision:literal, $scale:literal, $min:literal, $max:literal) => {
+        (::rust_decimal::Decimal::from($min), ::rust_decimal::Decimal::from($max))
+    };
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident: NUMERIC($precision:literal, $scale:literal);
+        noun = $noun:literal, expecting = $expecting:literal $(, range = $min:literal ..= $max:literal)?;
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, ::diesel::expression::AsExpression, ::diesel::deserialize::FromSqlRow)]
+        #[diesel(sql_type = ::diesel::sql_types::Numeric)]
+        pub struct $name(::rust_decimal::Decimal);
+
+        impl $name {
+            pub const ZERO: $name = $name(::rust_decimal::Decimal::ZERO);
+
+            /// Decimal places kept, the column's scale.
+            pub const SCALE: u32 = $scale;
+
+            /// Rounds half away from zero to `SCALE` decimals, the same rule
+            /// Postgres applies to `round()` and to `NUMERIC` columns.
+            fn rounded(value: ::rust_decimal::Decimal) -> $name {
+                let mut value = value.round_dp_with_strategy($scale, ::rust_decimal::RoundingStrategy::MidpointAwayFromZero);
+                value.rescale($scale);
+                $name(value)
+            }
+
+            /// The smallest and largest values `parse` accepts.
+            pub fn range() -> ($name, $name) {
+                let (min, max) = decimal_type!(@range $precision, $scale $(, $min, $max)?);
+
+                ($name::rounded(min), $name::rounded(max))
+            }
+
+            /// Parses a client supplied value, which is never rounded.
+            pub fn parse(value: &str) -> Result<$name, String> {
+                let parsed = <::rust_decimal::Decimal as ::std::str::FromStr>::from_str(value.trim())
+                    .map_err(|_| format!("`{}` is not a valid {}", value, $noun))?;
+
+                if parsed.normalize().scale() > $scale {
+                    return Err(format!("`{}` has more than {} decimal places", value, $scale));
+                }
+
+                let parsed = $name::rounded(parsed);
+                let (min, max) = $name::range();
+                if parsed < min || parsed > max {
+                    return Err(format!("`{}` is not between {} and {}", value, min, max));
+                }
+
+                Ok(parsed)
+            }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                write!(f, "{:.*}", $scale, self.0)
+            }
+        }
+
+        impl ::diesel::serialize::ToSql<::diesel::sql_types::Numeric, ::diesel::pg::Pg> for $name {
+            fn to_sql<'b>(&'b self, out: &mut ::diesel::serialize::Output<'b, '_, ::diesel::pg::Pg>) -> ::diesel::serialize::Result {
+                <::rust_decimal::Decimal as ::diesel::serialize::ToSql<::diesel::sql_types::Numeric, ::diesel::pg::Pg>>::to_sql(&self.0, &mut out.reborrow())
+            }
+        }
+
+        impl ::diesel::deserialize::FromSql<::diesel::sql_types::Numeric, ::diesel::pg::Pg> for $name {
+            fn from_sql(bytes: ::diesel::pg::PgValue<'_>) -> ::diesel::deserialize::Result<Self> {
+                <::rust_decimal::Decimal as ::diesel::deserialize::FromSql<::diesel::sql_types::Numeric, ::diesel::pg::Pg>>::from_sql(bytes).map($name::rounded)
+            }
+        }
+
+        impl ::serde::Serialize for $name {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<$name, D::Error> {
+                use ::serde::de::{Error, Visitor};
+
+                struct DecimalVisitor;
+
+                impl Visitor<'_> for DecimalVisitor {
+                    type Value = $name;
+
+                    fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                        f.write_str($expecting)
+                    }
+
+                    fn visit_str<E: Error>(self, value: &str) -> Result<$name, E> {
+                        $name::parse(value).map_err(E::custom)
+                    }
+
+                    fn visit_i64<E: Error>(self, value: i64) -> Result<$name, E> {
+                        $name::parse(&value.to_string()).map_err(E::custom)
+                    }
+
+                    fn visit_u64<E: Error>(self, value: u64) -> Result<$name, E> {
+                        $name::parse(&value.to_string()).map_err(E::custom)
+                    }
+
+                    // goes through the float's shortest representation, so 0.1 stays 0.1
+                    fn visit_f64<E: Error>(self, value: f64) -> Result<$name, E> {
+                        $name::parse(&value.to_string()).map_err(E::custom)
+                    }
+                }
+
+                deserializer.deserialize_any(DecimalVisitor)
+            }
+        }
+
+        #[::rocket::async_trait]
+        impl<'v> ::rocket::form::FromFormField<'v> for $name {
+            fn from_value(field: ::rocket::form::ValueField<'v>) -> ::rocket::form::Result<'v, Self> {
+                Ok($name::parse(field.value).map_err(::rocket::form::Error::validation)?)
+            }
+        }
+    };
+}
+
+pub(crate) use decimal_type;
@@ -0,0 +1,213 @@
+use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind, Error as DieselError, QueryResult};
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket::serde::json::serde_json::Map;
+use rocket::serde::json::{json, Json, Value};
+
+/// Error type shared by every route.
+///
+/// Rendered as `{"code": ..., "message": ..., "details": ...}` with the matching
+/// HTTP status, the same shape the catchers below use for Rocket's own errors.
+#[derive(Debug, Clone)]
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    UnsupportedMediaType(String),
+    Validation(String, Option<Value>),
+    Conflict(String, Option<Value>),
+    UniqueViolation(String, Option<Value>),
+    ForeignKeyViolation(String, Option<Value>),
+    CheckViolation(String, Option<Value>),
+    SerializationFailure(String),
+    ServiceUnavailable(String),
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::UnsupportedMediaType(_) => Status::UnsupportedMediaType,
+            ApiError::Validation(..) => Status::UnprocessableEntity,
+            ApiError::Conflict(..) => Status::Conflict,
+            ApiError::UniqueViolation(..) => Status::Conflict,
+            ApiError::ForeignKeyViolation(..) => Status::Conflict,
+            ApiError::CheckViolation(..) => Status::UnprocessableEntity,
+            ApiError::SerializationFailure(_) => Status::Conflict,
+            ApiError::ServiceUnavailable(_) => Status::ServiceUnavailable,
+            ApiError::Internal(_) => Status::InternalServerError,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::UnsupportedMediaType(_) => "unsupported_media_type",
+            ApiError::Validation(..) => "validation_failed",
+            ApiError::Conflict(..) => "conflict",
+            ApiError::UniqueViolation(..) => "unique_violation",
+            ApiError::ForeignKeyViolation(..) => "foreign_key_violation",
+            ApiError::CheckViolation(..) => "check_violation",
+            ApiError::SerializationFailure(_) => "serialization_failure",
+            ApiError::ServiceUnavailable(_) => "service_unavailable",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::NotFound(message)
+            | ApiError::UnsupportedMediaType(message)
+            | ApiError::Validation(message, _)
+            | ApiError::Conflict(message, _)
+            | ApiError::UniqueViolation(message, _)
+            | ApiError::ForeignKeyViolation(message, _)
+            | ApiError::CheckViolation(message, _)
+            | ApiError::SerializationFailure(message)
+            | ApiError::ServiceUnavailable(message)
+            | ApiError::Internal(message) => message,
+        }
+    }
+
+    pub fn details(&self) -> Option<&Value> {
+        match self {
+            ApiError::Validation(_, details)
+            | ApiError::Conflict(_, details)
+            | ApiError::UniqueViolation(_, details)
+            | ApiError::ForeignKeyViolation(_, details)
+            | ApiError::CheckViolation(_, details) => details.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn not_found(resource: &str) -> ApiError {
+        ApiError::NotFound(format!("{} not found", resource))
+    }
+
+    /// Prefixes the message and merges `context` into the details, for errors
+    /// raised while handling one part of a bigger request body.
+    pub fn with_context(self, prefix: &str, context: Value) -> ApiError {
+        let message = format!("{}: {}", prefix, self.message());
+
+        let mut details = match self.details() {
+            Some(Value::Object(details)) => details.clone(),
+            Some(other) => Map::from_iter([("details".to_string(), other.clone())]),
+            None => Map::new(),
+        };
+        if let Value::Object(context) = context {
+            details.extend(context);
+        }
+        let details = Some(Value::Object(details));
+
+        match self {
+            ApiError::BadRequest(_) => ApiError::BadRequest(message),
+            ApiError::NotFound(_) => ApiError::NotFound(message),
+            ApiError::UnsupportedMediaType(_) => ApiError::UnsupportedMediaType(message),
+            ApiError::Validation(..) => ApiError::Validation(message, details),
+            ApiError::Conflict(..) => ApiError::Conflict(message, details),
+            ApiError::UniqueViolation(..) => ApiError::UniqueViolation(message, details),
+            ApiError::ForeignKeyViolation(..) => ApiError::ForeignKeyViolation(message, details),
+            ApiError::CheckViolation(..) => ApiError::CheckViolation(message, details),
+            ApiError::SerializationFailure(_) => ApiError::SerializationFailure(message),
+            ApiError::ServiceUnavailable(_) => ApiError::ServiceUnavailable(message),
+            ApiError::Internal(_) => ApiError::Internal(message),
+        }
+    }
+
+    /// Keeps the error around for `default_catcher`, for guards that can only
+    /// hand Rocket a status.
+    pub fn stash(self, request: &Request<'_>) -> ApiError {
+        request.local_cache(|| Some(self.clone()));
+        self
+    }
+}
+
+fn database_details(info: &dyn DatabaseErrorInformation) -> Option<Value> {
+    Some(json!({
+        "table": info.table_name(),
+        "column": info.column_name(),
+        "constraint": info.constraint_name(),
+        "detail": info.details().unwrap_or_else(|| info.message()),
+    }))
+}
+
+impl From<DieselError> for ApiError {
+    fn from(err: DieselError) -> Self {
+        match err {
+            DieselError::NotFound => ApiError::not_found("Record"),
+            DieselError::DatabaseError(kind, info) => match kind {
+                DatabaseErrorKind::UniqueViolation => ApiError::UniqueViolation(
+                    "A record with the same unique value already exists".to_string(),
+                    database_details(info.as_ref()),
+                ),
+                DatabaseErrorKind::ForeignKeyViolation => ApiError::ForeignKeyViolation(
+                    "The record references a missing record or is still referenced by another one".to_string(),
+                    database_details(info.as_ref()),
+                ),
+                DatabaseErrorKind::CheckViolation | DatabaseErrorKind::NotNullViolation => ApiError::CheckViolation(
+                    "A value is outside of its allowed range".to_string(),
+                    database_details(info.as_ref()),
+                ),
+                DatabaseErrorKind::SerializationFailure => ApiError::SerializationFailure(
+                    "The record was changed concurrently, please retry".to_string(),
+                ),
+                DatabaseErrorKind::ClosedConnection => ApiError::ServiceUnavailable(
+                    "The database is unavailable".to_string(),
+                ),
+                _ => ApiError::Internal(info.message().to_string()),
+            },
+            err => ApiError::Internal(err.to_string()),
+        }
+    }
+}
+
+/// Lets handlers name the missing resource: `_get_item(&mut conn, id).or_not_found("Item")?`.
+pub trait OrNotFound<T> {
+    fn or_not_found(self, resource: &str) -> Result<T, ApiError>;
+}
+
+impl<T> OrNotFound<T> for QueryResult<T> {
+    fn or_not_found(self, resource: &str) -> Result<T, ApiError> {
+        self.map_err(|err| match err {
+            DieselError::NotFound => ApiError::not_found(resource),
+            err => ApiError::from(err),
+        })
+    }
+}
+
+fn error_body(code: &str, message: &str, details: Option<&Value>) -> Json<Value> {
+    Json(json!({
+        "code": code,
+        "message": message,
+        "details": details,
+    }))
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        if let ApiError::Internal(message) = &self {
+            error!("{} {}: {}", request.method(), request.uri(), message);
+        }
+
+        let body = error_body(self.code(), self.message(), self.details());
+        (self.status(), body).respond_to(request)
+    }
+}
+
+/// Renders errors raised by Rocket itself (unknown routes, failed guards,
+/// unparsable bodies) with the same JSON shape as `ApiError`.
+#[catch(default)]
+pub fn default_catcher(status: Status, request: &Request) -> (Status, Json<Value>) {
+    if let Some(err) = request.local_cache(|| None::<ApiError>) {
+        return (status, error_body(err.code(), err.message(), err.details()));
+    }
+
+    let reason = status.reason().unwrap_or("Unknown Error");
+    let code = reason.to_lowercase().replace(' ', "_");
+
+    (status, error_body(&code, reason, None))
+}
@@ -5,4 +5,17 @@ pub mod motoboy;
 pub mod order_details;
 pub mod customer;
 pub mod customer_address;
-pub mod customer_order;
\ No newline at end of file
+pub mod customer_merge;
+pub mod customer_history;
+pub mod customer_order;
+pub mod order_status;
+pub mod sales_channel;
+pub mod report;
+pub mod full_order;
+pub mod item_category;
+pub mod menu;
+pub mod modifier;
+pub mod combo;
+pub mod inventory;
+pub mod availability;
+pub mod item_price;
@@ -1,11 +1,16 @@
 use crate::schema::address;
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::result::OptionalEmptyChangesetExtension;
 use rocket::serde::json::Json;
-use rocket::form::{Form, FromForm};
-use crate::DATABASE_URL;
+use rocket::form::FromForm;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::money::Money;
+use crate::pagination::{contains_pattern, parse_sort, sort_by, ListQuery, Page, PageParams, SortKey};
+use crate::payload::{double_option, Payload};
 use crate::libs::neighborhood::_get_neighborhood;
-use rocket::response::status;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 
 
@@ -17,10 +22,10 @@ pub struct Address {
     pub neighborhood_id: i32,
     pub complement: Option<String>,
     pub observation: Option<String>,
-    pub delivery_fee: f64,
+    pub delivery_fee: Money,
 }
 
-#[derive(Debug, AsChangeset, Insertable, FromForm)]
+#[derive(Debug, AsChangeset, Insertable, FromForm, Deserialize)]
 #[diesel(table_name = address)]
 pub struct NewAddress {
     pub street: String,
@@ -28,89 +33,114 @@ pub struct NewAddress {
     pub neighborhood_id: i32,
     pub complement: Option<String>,
     pub observation: Option<String>,
-    pub delivery_fee: Option<f64>,
+    pub delivery_fee: Option<Money>,
 }
 
-#[get("/address/<address_id>")]
-pub fn get_address(address_id: i32) -> Result<Json<Address>, status::NotFound<String>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+#[derive(Debug, AsChangeset, FromForm, Deserialize)]
+#[diesel(table_name = address)]
+pub struct AddressChangeset {
+    pub street: Option<String>,
+    pub number: Option<String>,
+    pub neighborhood_id: Option<i32>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub complement: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub observation: Option<Option<String>>,
+    pub delivery_fee: Option<Money>,
+}
 
-    let address = _get_address(&mut conn, address_id);
+#[get("/address/<address_id>")]
+pub fn get_address(mut conn: DbConn, address_id: i32) -> Result<Json<Address>, ApiError> {
+    let address = _get_address(&mut conn, address_id).or_not_found("Address")?;
 
-    match address {
-        Some(address) => Ok(Json(address)),
-        None => Err(status::NotFound("Address not found".to_string())),
-    }
+    Ok(Json(address))
 }
 
-fn _get_address(conn: &mut PgConnection, address_id: i32) -> Option<Address> {
+pub fn _get_address(conn: &mut PgConnection, address_id: i32) -> QueryResult<Address> {
     address::table
         .find(address_id)
         .first::<Address>(conn)
-        .optional()
-        .unwrap()
 }
 
-#[get("/address")]
-pub fn get_addresses() -> Json<Vec<Address>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-    let addresses = _get_addresses(&mut conn);
-    Json(addresses)
+#[derive(Debug, FromForm)]
+pub struct AddressFilter {
+    pub street: Option<String>,
+    pub neighborhood_id: Option<i32>,
 }
 
-fn _get_addresses(conn: &mut PgConnection) -> Vec<Address> {
-    address::table
-        .load::<Address>(conn)
-        .unwrap()
+const ADDRESS_SORT_FIELDS: &[&str] = &["id", "street", "neighborhood_id", "delivery_fee"];
+
+#[get("/address?<query..>")]
+pub fn get_addresses(mut conn: DbConn, query: ListQuery<AddressFilter>) -> Result<Json<Page<Address>>, ApiError> {
+    let sort = parse_sort(query.page.sort.as_deref(), ADDRESS_SORT_FIELDS)?;
+    let addresses = _get_addresses(&mut conn, &query.filter, &query.page, &sort)?;
+    Ok(Json(addresses))
 }
 
-#[post("/address", data = "<address>")]
-pub fn create_address(mut address: Form<NewAddress>) -> Result<Json<Address>, status::BadRequest<String>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+fn filter_addresses(filter: &AddressFilter) -> address::BoxedQuery<'static, Pg> {
+    let mut query = address::table.into_boxed();
+
+    if let Some(street) = &filter.street {
+        query = query.filter(address::street.ilike(contains_pattern(street)));
+    }
+    if let Some(neighborhood_id) = filter.neighborhood_id {
+        query = query.filter(address::neighborhood_id.eq(neighborhood_id));
+    }
+
+    query
+}
+
+fn _get_addresses(conn: &mut PgConnection, filter: &AddressFilter, page: &PageParams, sort: &[SortKey]) -> QueryResult<Page<Address>> {
+    let total = filter_addresses(filter)
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let addresses = sort_by!(filter_addresses(filter), sort, {
+            "id" => address::id,
+            "street" => address::street,
+            "neighborhood_id" => address::neighborhood_id,
+            "delivery_fee" => address::delivery_fee,
+        })
+        .then_order_by(address::id)
+        .limit(page.limit)
+        .offset(page.offset)
+        .load::<Address>(conn)?;
+
+    Ok(Page::new(addresses, total, page))
+}
 
+#[post("/address", data = "<address>")]
+pub fn create_address(mut conn: DbConn, mut address: Payload<NewAddress>) -> Result<Json<Address>, ApiError> {
     // Check if delivery_fee form is empty
-    // let mut address = address.clone();
     if address.delivery_fee.is_none() {
         // Set delivery_fee to neighborhood default value
         address.delivery_fee = Some(_get_neighborhood(&mut conn, address.neighborhood_id)
-            .unwrap()
+            .or_not_found("Neighborhood")?
             .delivery_fee);
     }
 
-    let new_address = _create_address(&mut conn, address.into_inner());
+    let new_address = _create_address(&mut conn, address.into_inner())?;
 
-    match new_address {
-        Ok(address) => Ok(Json(address)),
-        Err(_) => Err(status::BadRequest(Some("Error creating address".to_string()))),
-    }
+    Ok(Json(new_address))
 }
 
-fn _create_address(conn: &mut PgConnection, address: NewAddress) -> QueryResult<Address> {
+pub fn _create_address(conn: &mut PgConnection, address: NewAddress) -> QueryResult<Address> {
     diesel::insert_into(address::table)
         .values(address)
         .get_result::<Address>(conn)
 }
 
 #[put("/address/<address_id>", data = "<address>")]
-pub fn update_address(address_id: i32, mut address: Form<NewAddress>) -> String {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+pub fn update_address(mut conn: DbConn, address_id: i32, mut address: Payload<NewAddress>) -> Result<Json<Address>, ApiError> {
     // Check if delivery_fee form is empty
-    // let mut address = address.clone();
     if address.delivery_fee.is_none() {
         // Set delivery_fee to neighborhood default value
         address.delivery_fee = Some(_get_neighborhood(&mut conn, address.neighborhood_id)
-            .unwrap()
+            .or_not_found("Neighborhood")?
             .delivery_fee);
     }
-    let updated_address = _update_address(&mut conn, address_id, address.into_inner());
-    match updated_address {
-        Ok(address) => format!("{:?}", address),
-        Err(_) => "Error updating address".to_string(),
-    }
+    let updated_address = _update_address(&mut conn, address_id, address.into_inner()).or_not_found("Address")?;
+    Ok(Json(updated_address))
 }
 
 fn _update_address(conn: &mut PgConnection, address_id: i32, address: NewAddress) -> QueryResult<Address> {
@@ -119,18 +149,31 @@ fn _update_address(conn: &mut PgConnection, address_id: i32, address: NewAddress
         .get_result::<Address>(conn)
 }
 
-#[delete("/address/<address_id>")]
-pub fn delete_address(address_id: i32) -> String {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-    let deleted_address = _delete_address(&mut conn, address_id);
-    match deleted_address {
-        Ok(_) => "Address deleted".to_string(),
-        Err(_) => "Error deleting address".to_string(),
+#[patch("/address/<address_id>", data = "<address>")]
+pub fn patch_address(mut conn: DbConn, address_id: i32, address: Payload<AddressChangeset>) -> Result<Json<Address>, ApiError> {
+    let updated_address = _patch_address(&mut conn, address_id, address.into_inner()).or_not_found("Address")?;
+    Ok(Json(updated_address))
+}
+
+fn _patch_address(conn: &mut PgConnection, address_id: i32, address: AddressChangeset) -> QueryResult<Address> {
+    let updated = diesel::update(address::table.find(address_id))
+        .set(&address)
+        .get_result::<Address>(conn)
+        .optional_empty_changeset()?;
+
+    match updated {
+        Some(address) => Ok(address),
+        None => _get_address(conn, address_id),
     }
 }
 
-fn _delete_address(conn: &mut PgConnection, address_id: i32) -> QueryResult<usize> {
+#[delete("/address/<address_id>")]
+pub fn delete_address(mut conn: DbConn, address_id: i32) -> Result<Json<Address>, ApiError> {
+    let deleted_address = _delete_address(&mut conn, address_id).or_not_found("Address")?;
+    Ok(Json(deleted_address))
+}
+
+fn _delete_address(conn: &mut PgConnection, address_id: i32) -> QueryResult<Address> {
     diesel::delete(address::table.find(address_id))
-        .execute(conn)
+        .get_result::<Address>(conn)
 }
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use crate::schema::availability_window;
+use chrono::{DateTime, Datelike, Timelike};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, Int4};
+use rocket::form::FromForm;
+use rocket::serde::json::Json;
+use rocket::time::{Duration, Time};
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::libs::item::{Item, _get_item};
+use crate::libs::item_category::_get_item_category;
+use crate::payload::Payload;
+use serde::{Deserialize, Serialize};
+
+
+/// A weekly time range an item, or every item of a category, is served in.
+/// Items with windows of their own ignore their category's.
+#[derive(Debug, Queryable, Serialize)]
+pub struct AvailabilityWindow {
+    pub id: i32,
+    pub item_id: Option<i32>,
+    pub category_id: Option<i32>,
+    pub weekday: i16,
+    #[serde(with = "clock_time")]
+    pub start_time: Time,
+    #[serde(with = "clock_time")]
+    pub end_time: Time,
+}
+
+/// `weekday` is ISO (1 Monday to 7 Sunday) and times are in the store
+/// timezone, as `"HH:MM"`. A window ending before its start runs past midnight.
+#[derive(Debug, AsChangeset, Insertable, FromForm, Deserialize)]
+#[diesel(table_name = availability_window)]
+pub struct NewAvailabilityWindow {
+    pub weekday: i16,
+    #[serde(with = "clock_time")]
+    pub start_time: Time,
+    #[serde(with = "clock_time")]
+    pub end_time: Time,
+}
+
+mod clock_time {
+    use rocket::time::Time;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &Time, serializer: S) -> Result<S::Ok, S::Error> {
+        match time.second() {
+            0 => serializer.collect_str(&format_args!("{:02}:{:02}", time.hour(), time.minute())),
+            second => serializer.collect_str(&format_args!("{:02}:{:02}:{:02}", time.hour(), time.minute(), second)),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Time, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        let parts = value.split(':')
+            .map(|part| part.parse::<u8>().ok())
+            .collect::<Option<Vec<_>>>();
+
+        let time = match parts.as_deref() {
+            Some([hour, minute]) => Time::from_hms(*hour, *minute, 0).ok(),
+            Some([hour, minute, second]) => Time::from_hms(*hour, *minute, *second).ok(),
+            _ => None,
+        };
+
+        time.ok_or_else(|| de::Error::custom(format!("`{}` is not a time such as \"07:30\"", value)))
+    }
+}
+
+impl AvailabilityWindow {
+    fn covers(&self, weekday: i16, time: Time) -> bool {
+        if self.start_time < self.end_time {
+            self.weekday == weekday && self.start_time <= time && time < self.end_time
+        } else {
+            let next_day = self.weekday % 7 + 1;
+
+            (self.weekday == weekday && time >= self.start_time)
+                || (next_day == weekday && time < self.end_time)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnavailableReason {
+    /// Switched off by hand with `is_active`.
+    Inactive,
+    /// Outside every serving window of the item or its category.
+    OutsideSchedule,
+    /// Some ingredient of its recipe, or of a combo component's, is short for one unit.
+    OutOfStock,
+}
+
+impl UnavailableReason {
+    pub fn describe(self) -> &'static str {
+        match self {
+            UnavailableReason::Inactive => "inactive",
+            UnavailableReason::OutsideSchedule => "not served at this time",
+            UnavailableReason::OutOfStock => "out of stock",
+        }
+    }
+}
+
+/// An item with its availability right now, what `GET /item` returns.
+#[derive(Debug, Serialize)]
+pub struct ItemWithAvailability {
+    #[serde(flatten)]
+    pub item: Item,
+    pub available: bool,
+    pub unavailable_reason: Option<UnavailableReason>,
+}
+
+#[derive(Debug, QueryableByName)]
+struct ShortItem {
+    #[diesel(sql_type = Int4)]
+    item_id: i32,
+}
+
+/// Why each of `items` can't be ordered at `now` besides being inactive,
+/// keyed by item id. Available items are left out.
+pub fn _get_unavailable_reasons(conn: &mut PgConnection, items: &[Item], now: &DateTime<Tz>) -> QueryResult<HashMap<i32, UnavailableReason>> {
+    let item_ids = items.iter().map(|item| item.id).collect::<Vec<_>>();
+    let category_ids = items.iter().filter_map(|item| item.category_id).collect::<Vec<_>>();
+
+    let windows = availability_window::table
+        .filter(availability_window::item_id.eq_any(&item_ids)
+            .or(availability_window::category_id.eq_any(&category_ids)))
+        .load::<AvailabilityWindow>(conn)?;
+
+    let short = diesel::sql_query("
+        SELECT DISTINCT u.item_id
+        FROM (
+            SELECT r.item_id, r.ingredient_id, r.quantity
+            FROM recipe_ingredient r
+            WHERE r.item_id = ANY($1)
+            UNION ALL
+            SELECT c.combo_item_id, r.ingredient_id, r.quantity * c.quantity
+            FROM combo_component c
+            JOIN recipe_ingredient r ON r.item_id = c.item_id
+            WHERE c.combo_item_id = ANY($1)
+        ) u
+        JOIN ingredient i ON i.id = u.ingredient_id
+        GROUP BY u.item_id, u.ingredient_id, i.stock
+        HAVING sum(u.quantity) > i.stock
+    ")
+        .bind::<Array<Int4>, _>(&item_ids)
+        .load::<ShortItem>(conn)?;
+
+    let weekday = now.weekday().number_from_monday() as i16;
+    let time = Time::MIDNIGHT + Duration::seconds(now.num_seconds_from_midnight().into());
+
+    let mut reasons = HashMap::new();
+    for item in items {
+        let own = windows.iter().filter(|window| window.item_id == Some(item.id)).collect::<Vec<_>>();
+        let schedule = if own.is_empty() {
+            windows.iter().filter(|window| window.category_id.is_some() && window.category_id == item.category_id).collect()
+        } else {
+            own
+        };
+
+        if !schedule.is_empty() && !schedule.iter().any(|window| window.covers(weekday, time)) {
+            reasons.insert(item.id, UnavailableReason::OutsideSchedule);
+        } else if short.iter().any(|short| short.item_id == item.id) {
+            reasons.insert(item.id, UnavailableReason::OutOfStock);
+        }
+    }
+
+    Ok(reasons)
+}
+
+pub fn _with_availability(conn: &mut PgConnection, items: Vec<Item>, now: &DateTime<Tz>) -> QueryResult<Vec<ItemWithAvailability>> {
+    let reasons = _get_unavailable_reasons(conn, &items, now)?;
+
+    Ok(items
+        .into_iter()
+        .map(|item| {
+            let reason = if item.is_active {
+                reasons.get(&item.id).copied()
+            } else {
+                Some(UnavailableReason::Inactive)
+            };
+
+            ItemWithAvailability { item, available: reason.is_none(), unavailable_reason: reason }
+        })
+        .collect())
+}
+
+#[get("/item/<item_id>/availability")]
+pub fn get_item_availability_windows(mut conn: DbConn, item_id: i32) -> Result<Json<Vec<AvailabilityWindow>>, ApiError> {
+    _get_item(&mut conn, item_id).or_not_found("Item")?;
+    let windows = _get_item_availability_windows(&mut conn, item_id)?;
+
+    Ok(Json(windows))
+}
+
+pub fn _get_item_availability_windows(conn: &mut PgConnection, item_id: i32) -> QueryResult<Vec<AvailabilityWindow>> {
+    availability_window::table
+        .filter(availability_window::item_id.eq(item_id))
+        .order((availability_window::weekday, availability_window::start_time, availability_window::id))
+        .load::<AvailabilityWindow>(conn)
+}
+
+#[post("/item/<item_id>/availability", data = "<window>")]
+pub fn create_item_availability_window(mut conn: DbConn, item_id: i32, window: Payload<NewAvailabilityWindow>) -> Result<Json<AvailabilityWindow>, ApiError> {
+    _get_item(&mut conn, item_id).or_not_found("Item")?;
+    let window = _create_item_availability_window(&mut conn, item_id, window.into_inner())?;
+
+    Ok(Json(window))
+}
+
+fn _create_item_availability_window(conn: &mut PgConnection, item_id: i32, window: NewAvailabilityWindow) -> QueryResult<AvailabilityWindow> {
+    diesel::insert_into(availability_window::table)
+        .values((window, availability_window::item_id.eq(item_id)))
+        .get_result::<AvailabilityWindow>(conn)
+}
+
+#[get("/item_category/<category_id>/availability")]
+pub fn get_category_availability_windows(mut conn: DbConn, category_id: i32) -> Result<Json<Vec<AvailabilityWindow>>, ApiError> {
+    _get_item_category(&mut conn, category_id).or_not_found("Item category")?;
+    let windows = _get_category_availability_windows(&mut conn, category_id)?;
+
+    Ok(Json(windows))
+}
+
+pub fn _get_category_availability_windows(conn: &mut PgConnection, category_id: i32) -> QueryResult<Vec<AvailabilityWindow>> {
+    availability_window::table
+        .filter(availability_window::category_id.eq(category_id))
+        .order((availability_window::weekday, availability_window::start_time, availability_window::id))
+        .load::<AvailabilityWindow>(conn)
+}
+
+#[post("/item_category/<category_id>/availability", data = "<window>")]
+pub fn create_category_availability_window(mut conn: DbConn, category_id: i32, window: Payload<NewAvailabilityWindow>) -> Result<Json<AvailabilityWindow>, ApiError> {
+    _get_item_category(&mut conn, category_id).or_not_found("Item category")?;
+    let window = _create_category_availability_window(&mut conn, category_id, window.into_inner())?;
+
+    Ok(Json(window))
+}
+
+fn _create_category_availability_window(conn: &mut PgConnection, category_id: i32, window: NewAvailabilityWindow) -> QueryResult<AvailabilityWindow> {
+    diesel::insert_into(availability_window::table)
+        .values((window, availability_window::category_id.eq(category_id)))
+        .get_result::<AvailabilityWindow>(conn)
+}
+
+#[put("/availability_window/<window_id>", data = "<window>")]
+pub fn update_availability_window(mut conn: DbConn, window_id: i32, window: Payload<NewAvailabilityWindow>) -> Result<Json<AvailabilityWindow>, ApiError> {
+    let window = _update_availability_window(&mut conn, window_id, window.into_inner()).or_not_found("Availability window")?;
+
+    Ok(Json(window))
+}
+
+fn _update_availability_window(conn: &mut PgConnection, window_id: i32, window: NewAvailabilityWindow) -> QueryResult<AvailabilityWindow> {
+    diesel::update(availability_window::table.find(window_id))
+        .set(window)
+        .get_result::<AvailabilityWindow>(conn)
+}
+
+#[delete("/availability_window/<window_id>")]
+pub fn delete_availability_window(mut conn: DbConn, window_id: i32) -> Result<Json<AvailabilityWindow>, ApiError> {
+    let window = _delete_availability_window(&mut conn, window_id).or_not_found("Availability window")?;
+
+    Ok(Json(window))
+}
+
+fn _delete_availability_window(conn: &mut PgConnection, window_id: i32) -> QueryResult<AvailabilityWindow> {
+    diesel::delete(availability_window::table.find(window_id))
+        .get_result::<AvailabilityWindow>(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(weekday: i16, start: (u8, u8), end: (u8, u8)) -> AvailabilityWindow {
+        AvailabilityWindow {
+            id: 1,
+            item_id: Some(1),
+            category_id: None,
+            weekday,
+            start_time: Time::from_hms(start.0, start.1, 0).unwrap(),
+            end_time: Time::from_hms(end.0, end.1, 0).unwrap(),
+        }
+    }
+
+    fn at(hour: u8, minute: u8) -> Time {
+        Time::from_hms(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn daytime_window_covers_its_start_but_not_its_end() {
+        let lunch = window(1, (11, 0), (15, 0));
+
+        assert!(lunch.covers(1, at(11, 0)));
+        assert!(lunch.covers(1, at(14, 59)));
+        assert!(!lunch.covers(1, at(15, 0)));
+        assert!(!lunch.covers(1, at(10, 59)));
+    }
+
+    #[test]
+    fn daytime_window_only_covers_its_weekday() {
+        let lunch = window(1, (11, 0), (15, 0));
+
+        assert!(!lunch.covers(2, at(12, 0)));
+        assert!(!lunch.covers(7, at(12, 0)));
+    }
+
+    #[test]
+    fn overnight_window_runs_into_the_next_day() {
+        let late = window(5, (18, 0), (2, 0));
+
+        assert!(late.covers(5, at(18, 0)));
+        assert!(late.covers(5, at(23, 59)));
+        assert!(late.covers(6, at(0, 0)));
+        assert!(late.covers(6, at(1, 59)));
+        assert!(!late.covers(6, at(2, 0)));
+        assert!(!late.covers(5, at(1, 0)));
+        assert!(!late.covers(6, at(18, 30)));
+        assert!(!late.covers(5, at(17, 59)));
+    }
+
+    #[test]
+    fn overnight_window_on_sunday_runs_into_monday() {
+        let late = window(7, (22, 0), (3, 0));
+
+        assert!(late.covers(7, at(23, 0)));
+        assert!(late.covers(1, at(2, 0)));
+        assert!(!late.covers(1, at(23, 0)));
+    }
+
+    #[test]
+    fn window_ending_at_midnight_covers_until_the_end_of_the_day() {
+        let evening = window(3, (18, 0), (0, 0));
+
+        assert!(evening.covers(3, at(23, 59)));
+        assert!(!evening.covers(4, at(0, 0)));
+    }
+}
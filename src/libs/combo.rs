@@ -0,0 +1,375 @@
+use crate::schema::{combo_component, combo_component_swap, item};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use rocket::form::FromForm;
+use rocket::serde::json::{json, Json, Value};
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::libs::availability::_get_unavailable_reasons;
+use crate::libs::item::{Item, OrderOverrides, _get_item, _get_item_price};
+use crate::libs::item_price::to_offset_date_time;
+use crate::money::Money;
+use crate::payload::Payload;
+use serde::{Deserialize, Serialize};
+
+
+/// One slot of a combo, e.g. "1 x Soda" in "Burger + Fries + Soda".
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct ComboComponent {
+    pub id: i32,
+    pub combo_item_id: i32,
+    pub item_id: i32,
+    pub quantity: i32,
+    pub display_order: i32,
+}
+
+#[derive(Debug, FromForm, Deserialize)]
+pub struct NewComboComponent {
+    pub item_id: i32,
+    #[field(default = 1)]
+    #[serde(default = "one")]
+    pub quantity: i32,
+    #[field(default = 0)]
+    #[serde(default)]
+    pub display_order: i32,
+}
+
+fn one() -> i32 {
+    1
+}
+
+/// An item a component may be replaced with, at `price_delta` on the combo price.
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct ComponentSwapChoice {
+    pub item_id: i32,
+    pub item_name: String,
+    pub price_delta: Money,
+}
+
+#[derive(Debug, FromForm, Deserialize)]
+pub struct NewComponentSwapChoice {
+    pub item_id: i32,
+    #[field(default = Money::ZERO)]
+    #[serde(default)]
+    pub price_delta: Money,
+}
+
+/// A component as listed on its combo, with its item's name and swap choices.
+#[derive(Debug, Serialize)]
+pub struct ItemComponent {
+    #[serde(flatten)]
+    pub component: ComboComponent,
+    pub item_name: String,
+    pub swaps: Vec<ComponentSwapChoice>,
+}
+
+#[get("/item/<item_id>/component")]
+pub fn get_item_components(mut conn: DbConn, item_id: i32) -> Result<Json<Vec<ItemComponent>>, ApiError> {
+    _get_item(&mut conn, item_id).or_not_found("Item")?;
+    let components = _get_item_components(&mut conn, item_id)?;
+
+    Ok(Json(components))
+}
+
+/// The components of a combo in display order. Empty for plain items.
+pub fn _get_item_components(conn: &mut PgConnection, item_id: i32) -> QueryResult<Vec<ItemComponent>> {
+    let components = combo_component::table
+        .inner_join(item::table.on(item::id.eq(combo_component::item_id)))
+        .filter(combo_component::combo_item_id.eq(item_id))
+        .order((combo_component::display_order, combo_component::id))
+        .select((combo_component::all_columns, item::name))
+        .load::<(ComboComponent, String)>(conn)?;
+
+    let component_ids = components.iter().map(|(component, _)| component.id).collect::<Vec<_>>();
+    let swaps = combo_component_swap::table
+        .inner_join(item::table)
+        .filter(combo_component_swap::component_id.eq_any(&component_ids))
+        .order((combo_component_swap::component_id, item::name, item::id))
+        .select((combo_component_swap::component_id, item::id, item::name, combo_component_swap::price_delta))
+        .load::<(i32, i32, String, Money)>(conn)?;
+
+    Ok(components
+        .into_iter()
+        .map(|(component, item_name)| ItemComponent {
+            swaps: swaps.iter()
+                .filter(|(component_id, ..)| *component_id == component.id)
+                .map(|(_, item_id, item_name, price_delta)| ComponentSwapChoice {
+                    item_id: *item_id,
+                    item_name: item_name.clone(),
+                    price_delta: *price_delta,
+                })
+                .collect(),
+            component,
+            item_name,
+        })
+        .collect())
+}
+
+fn _get_combo_component(conn: &mut PgConnection, item_id: i32, component_id: i32) -> QueryResult<ComboComponent> {
+    combo_component::table
+        .find(component_id)
+        .filter(combo_component::combo_item_id.eq(item_id))
+        .first::<ComboComponent>(conn)
+}
+
+fn is_combo(conn: &mut PgConnection, item_id: i32) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        combo_component::table.filter(combo_component::combo_item_id.eq(item_id))
+    ))
+        .get_result::<bool>(conn)
+}
+
+fn is_component(conn: &mut PgConnection, item_id: i32) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        combo_component::table.filter(combo_component::item_id.eq(item_id))
+    ))
+        .get_result::<bool>(conn)
+}
+
+/// Combos are one level deep: a combo can't be part of another combo, and an
+/// item used as a component can't become a combo.
+fn ensure_composable(conn: &mut PgConnection, combo_item_id: i32, component_item_id: i32) -> Result<(), ApiError> {
+    _get_item(conn, component_item_id)
+        .optional()?
+        .ok_or_else(|| ApiError::Validation(
+            format!("Item {} does not exist", component_item_id),
+            Some(json!({ "item_id": component_item_id })),
+        ))?;
+
+    if combo_item_id == component_item_id {
+        return Err(ApiError::Validation("A combo can't contain itself".to_string(), Some(json!({ "item_id": component_item_id }))));
+    }
+    if is_component(conn, combo_item_id)? {
+        return Err(ApiError::Validation(
+            format!("Item {} is part of a combo and can't have components", combo_item_id),
+            Some(json!({ "combo_item_id": combo_item_id })),
+        ));
+    }
+    if is_combo(conn, component_item_id)? {
+        return Err(ApiError::Validation(
+            format!("Item {} is a combo and can't be a component", component_item_id),
+            Some(json!({ "item_id": component_item_id })),
+        ));
+    }
+
+    Ok(())
+}
+
+#[post("/item/<item_id>/component", data = "<component>")]
+pub fn create_item_component(mut conn: DbConn, item_id: i32, component: Payload<NewComboComponent>) -> Result<Json<Vec<ItemComponent>>, ApiError> {
+    _get_item(&mut conn, item_id).or_not_found("Item")?;
+    ensure_composable(&mut conn, item_id, component.item_id)?;
+
+    _create_item_component(&mut conn, item_id, component.into_inner())?;
+    let components = _get_item_components(&mut conn, item_id)?;
+
+    Ok(Json(components))
+}
+
+fn _create_item_component(conn: &mut PgConnection, item_id: i32, component: NewComboComponent) -> QueryResult<ComboComponent> {
+    diesel::insert_into(combo_component::table)
+        .values((
+            combo_component::combo_item_id.eq(item_id),
+            combo_component::item_id.eq(component.item_id),
+            combo_component::quantity.eq(component.quantity),
+            combo_component::display_order.eq(component.display_order),
+        ))
+        .get_result::<ComboComponent>(conn)
+}
+
+#[put("/item/<item_id>/component/<component_id>", data = "<component>")]
+pub fn update_item_component(mut conn: DbConn, item_id: i32, component_id: i32, component: Payload<NewComboComponent>) -> Result<Json<Vec<ItemComponent>>, ApiError> {
+    _get_combo_component(&mut conn, item_id, component_id).or_not_found("Combo component")?;
+    ensure_composable(&mut conn, item_id, component.item_id)?;
+
+    _update_item_component(&mut conn, component_id, component.into_inner())?;
+    let components = _get_item_components(&mut conn, item_id)?;
+
+    Ok(Json(components))
+}
+
+fn _update_item_component(conn: &mut PgConnection, component_id: i32, component: NewComboComponent) -> QueryResult<ComboComponent> {
+    diesel::update(combo_component::table.find(component_id))
+        .set((
+            combo_component::item_id.eq(component.item_id),
+            combo_component::quantity.eq(component.quantity),
+            combo_component::display_order.eq(component.display_order),
+        ))
+        .get_result::<ComboComponent>(conn)
+}
+
+#[delete("/item/<item_id>/component/<component_id>")]
+pub fn delete_item_component(mut conn: DbConn, item_id: i32, component_id: i32) -> Result<Json<Vec<ItemComponent>>, ApiError> {
+    let deleted = diesel::delete(combo_component::table.find(component_id))
+        .filter(combo_component::combo_item_id.eq(item_id))
+        .execute(&mut *conn)?;
+    if deleted == 0 {
+        return Err(ApiError::not_found("Combo component"));
+    }
+
+    let components = _get_item_components(&mut conn, item_id)?;
+
+    Ok(Json(components))
+}
+
+#[post("/item/<item_id>/component/<component_id>/swap", data = "<swap>")]
+pub fn create_component_swap(mut conn: DbConn, item_id: i32, component_id: i32, swap: Payload<NewComponentSwapChoice>) -> Result<Json<Vec<ItemComponent>>, ApiError> {
+    let component = _get_combo_component(&mut conn, item_id, component_id).or_not_found("Combo component")?;
+    if swap.item_id != component.item_id {
+        ensure_composable(&mut conn, item_id, swap.item_id)?;
+    }
+
+    _create_component_swap(&mut conn, component_id, swap.into_inner())?;
+    let components = _get_item_components(&mut conn, item_id)?;
+
+    Ok(Json(components))
+}
+
+fn _create_component_swap(conn: &mut PgConnection, component_id: i32, swap: NewComponentSwapChoice) -> QueryResult<usize> {
+    diesel::insert_into(combo_component_swap::table)
+        .values((
+            combo_component_swap::component_id.eq(component_id),
+            combo_component_swap::item_id.eq(swap.item_id),
+            combo_component_swap::price_delta.eq(swap.price_delta),
+        ))
+        .on_conflict((combo_component_swap::component_id, combo_component_swap::item_id))
+        .do_update()
+        .set(combo_component_swap::price_delta.eq(swap.price_delta))
+        .execute(conn)
+}
+
+#[delete("/item/<item_id>/component/<component_id>/swap/<swap_item_id>")]
+pub fn delete_component_swap(mut conn: DbConn, item_id: i32, component_id: i32, swap_item_id: i32) -> Result<Json<Vec<ItemComponent>>, ApiError> {
+    _get_combo_component(&mut conn, item_id, component_id).or_not_found("Combo component")?;
+
+    let deleted = diesel::delete(combo_component_swap::table.find((component_id, swap_item_id)))
+        .execute(&mut *conn)?;
+    if deleted == 0 {
+        return Err(ApiError::not_found("Component swap"));
+    }
+
+    let components = _get_item_components(&mut conn, item_id)?;
+
+    Ok(Json(components))
+}
+
+/// Replaces component `component_id` of the ordered combo with `item_id`.
+#[derive(Debug, Clone, FromForm, Deserialize)]
+pub struct ComponentSwap {
+    pub component_id: i32,
+    pub item_id: i32,
+}
+
+/// A component as sent to the kitchen, with the name and list price to snapshot.
+#[derive(Debug)]
+pub struct KitchenComponent {
+    pub component_id: i32,
+    pub item_id: i32,
+    pub item_name: String,
+    pub quantity: i32,
+    pub unit_price: Money,
+}
+
+#[derive(Debug)]
+pub struct ComboSelection {
+    pub components: Vec<KitchenComponent>,
+    pub price_delta: Money,
+    /// Sorted `component:item` pairs of the swaps made, empty without swaps.
+    pub key: String,
+}
+
+fn swap_error(message: String, details: Value) -> ApiError {
+    ApiError::Validation(message, Some(details))
+}
+
+/// Applies the swaps asked for to the combo's components. Swapping a component
+/// for its own item is accepted and changes nothing. Plain items resolve to no
+/// components and refuse any swap.
+///
+/// Every component, swapped or not, must be orderable like the combo itself
+/// under `overrides`, and is priced as of `now`.
+pub fn _resolve_combo(conn: &mut PgConnection, item_id: i32, swaps: &[ComponentSwap], overrides: OrderOverrides, now: &DateTime<Tz>) -> Result<ComboSelection, ApiError> {
+    let mut swaps = swaps.to_vec();
+    swaps.sort_unstable_by_key(|swap| swap.component_id);
+    if let Some(pair) = swaps.windows(2).find(|pair| pair[0].component_id == pair[1].component_id) {
+        return Err(swap_error(
+            format!("Combo component {} is swapped more than once", pair[0].component_id),
+            json!({ "component_id": pair[0].component_id }),
+        ));
+    }
+
+    let components = _get_item_components(conn, item_id)?;
+
+    for swap in &swaps {
+        if !components.iter().any(|component| component.component.id == swap.component_id) {
+            return Err(swap_error(
+                format!("Combo component {} does not belong to this item", swap.component_id),
+                json!({ "component_id": swap.component_id }),
+            ));
+        }
+    }
+
+    let mut item_ids = components.iter().map(|component| component.component.item_id).collect::<Vec<_>>();
+    item_ids.extend(swaps.iter().map(|swap| swap.item_id));
+    let items = item::table
+        .filter(item::id.eq_any(&item_ids))
+        .load::<Item>(conn)?;
+    let unavailable = if overrides.allow_unavailable {
+        Default::default()
+    } else {
+        _get_unavailable_reasons(conn, &items, now)?
+    };
+
+    let mut selected = Vec::with_capacity(components.len());
+    let mut price_delta = Money::ZERO;
+    let mut key = Vec::new();
+    for ItemComponent { component, swaps: choices, .. } in &components {
+        let mut item_id = component.item_id;
+
+        let swap = swaps.iter().find(|swap| swap.component_id == component.id);
+        if let Some(swap) = swap.filter(|swap| swap.item_id != component.item_id) {
+            let choice = choices.iter()
+                .find(|choice| choice.item_id == swap.item_id)
+                .ok_or_else(|| swap_error(
+                    format!("Item {} cannot replace combo component {}", swap.item_id, component.id),
+                    json!({ "component_id": component.id, "item_id": swap.item_id }),
+                ))?;
+
+            item_id = swap.item_id;
+            price_delta = price_delta + choice.price_delta;
+            key.push(format!("{}:{}", component.id, swap.item_id));
+        }
+
+        let item = items.iter()
+            .find(|item| item.id == item_id)
+            .ok_or_else(|| ApiError::Internal(format!("Item {} of combo component {} is missing", item_id, component.id)))?;
+
+        if !item.is_active && !overrides.allow_inactive {
+            return Err(swap_error(
+                format!("{} is inactive, pass allow_inactive=true to order it anyway", item.name),
+                json!({ "component_id": component.id, "item_id": item.id }),
+            ));
+        }
+        if let Some(reason) = unavailable.get(&item.id) {
+            return Err(swap_error(
+                format!("{} is {}, pass allow_unavailable=true to order it anyway", item.name, reason.describe()),
+                json!({ "component_id": component.id, "item_id": item.id, "reason": reason }),
+            ));
+        }
+
+        selected.push(KitchenComponent {
+            component_id: component.id,
+            item_id: item.id,
+            item_name: item.name.clone(),
+            quantity: component.quantity,
+            unit_price: _get_item_price(conn, item.id, to_offset_date_time(now))?,
+        });
+    }
+
+    Ok(ComboSelection {
+        components: selected,
+        price_delta,
+        key: key.join(","),
+    })
+}
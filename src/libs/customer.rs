@@ -1,23 +1,37 @@
-use crate::schema::customer;
+use std::error::Error;
+
+use crate::schema::{customer, customer_order};
+use diesel::pg::Pg;
 use diesel::prelude::*;
-use diesel::result::Error;
-use rocket::form::{Form, FromForm};
-use rocket::http::Status;
-use crate::DATABASE_URL;
-use serde::Serialize;
-use rocket::serde::json::Json;
+use diesel::result::OptionalEmptyChangesetExtension;
+use diesel::sql_types::{Bool, Date as SqlDate, Float4, Int4, Int8, Nullable, Text, Varchar};
+use rocket::form::FromForm;
+use rocket::time::Date;
+use rocket::State;
+use crate::config::{AppConfig, PhoneConfig};
+use crate::db::{DbConn, DbPool};
+use crate::error::{ApiError, OrNotFound};
+use crate::libs::customer_address::{CustomerAddressEntry, _get_customer_addresses};
+use crate::libs::full_order::{FullOrder, _get_full_order};
+use crate::pagination::{contains_pattern, parse_sort, sort_by, ListQuery, Page, PageParams, SortKey};
+use crate::payload::{double_option, Payload};
+use crate::phone;
+use serde::{Deserialize, Serialize};
+use rocket::serde::json::{json, Json};
 
 
-#[derive(Debug, Queryable, Serialize)]
+#[derive(Debug, Clone, Queryable, Serialize)]
 pub struct Customer {
     pub id: i32,
     pub name: String,
     pub phone: Option<String>,
     pub address_id: i32,
+    /// Shared its phone with an older customer before phones had to be unique.
+    pub phone_duplicate: bool,
 }
 
 
-#[derive(Insertable, AsChangeset, FromForm)]
+#[derive(Insertable, AsChangeset, FromForm, Deserialize)]
 #[diesel(table_name = customer)]
 pub struct NewCustomer {
     pub name: String,
@@ -25,22 +39,21 @@ pub struct NewCustomer {
     pub address_id: i32,
 }
 
+#[derive(Debug, AsChangeset, FromForm, Deserialize)]
+#[diesel(table_name = customer)]
+pub struct CustomerChangeset {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub phone: Option<Option<String>>,
+    pub address_id: Option<i32>,
+}
+
 
 #[get("/customer/<customer_id>")]
-pub fn get_customer(customer_id: i32) -> Result<Json<Customer>, (Status, String)> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-
-    let customer = _get_customer(&mut conn, customer_id);
-
-    match customer {
-        Ok(customer) => Ok(Json(customer)),
-        Err(err) => match err {
-            Error::NotFound => Err((Status::NotFound, "Customer not found".to_string())),
-            Error::DatabaseError(_, info) => Err((Status::InternalServerError, info.message().to_string())),
-            _ => Err((Status::InternalServerError, "Internal server error".to_string())),
-        }
-    }
+pub fn get_customer(mut conn: DbConn, customer_id: i32) -> Result<Json<Customer>, ApiError> {
+    let customer = _get_customer(&mut conn, customer_id).or_not_found("Customer")?;
+
+    Ok(Json(customer))
 }
 
 pub fn _get_customer(conn: &mut PgConnection, customer_id: i32) -> QueryResult<Customer> {
@@ -50,77 +63,383 @@ pub fn _get_customer(conn: &mut PgConnection, customer_id: i32) -> QueryResult<C
 }
 
 #[post("/customer", data = "<customer>")]
-pub fn create_customer(customer: Form<NewCustomer>) -> String {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+pub fn create_customer(mut conn: DbConn, config: &State<AppConfig>, customer: Payload<NewCustomer>) -> Result<Json<Customer>, ApiError> {
+    let mut customer = customer.into_inner();
+    customer.phone = normalize_phone(customer.phone, &config.store.phone)?;
+    ensure_phone_available(&mut conn, customer.phone.as_deref(), None)?;
+    let customer = _create_customer(&mut conn, customer)?;
+
+    Ok(Json(customer))
+}
 
-    let customer = diesel::insert_into(customer::table)
-        .values(customer.into_inner())
-        .get_result::<Customer>(&mut conn);
+pub fn _create_customer(conn: &mut PgConnection, customer: NewCustomer) -> QueryResult<Customer> {
+    diesel::insert_into(customer::table)
+        .values(customer)
+        .get_result::<Customer>(conn)
+}
 
-    match customer {
-        Ok(customer) => format!("{:?}", customer),
-        Err(e) => format!("Error creating customer: {}", e),
+#[derive(Debug, FromForm)]
+pub struct CustomerFilter {
+    pub name: Option<String>,
+    pub phone: Option<String>,
+    pub address_id: Option<i32>,
+}
+
+const CUSTOMER_SORT_FIELDS: &[&str] = &["id", "name"];
+
+#[get("/customer?<query..>")]
+pub fn get_customers(mut conn: DbConn, query: ListQuery<CustomerFilter>) -> Result<Json<Page<Customer>>, ApiError> {
+    let sort = parse_sort(query.page.sort.as_deref(), CUSTOMER_SORT_FIELDS)?;
+    let customers = _get_customers(&mut conn, &query.filter, &query.page, &sort)?;
+
+    Ok(Json(customers))
+}
+
+fn filter_customers(filter: &CustomerFilter) -> customer::BoxedQuery<'static, Pg> {
+    let mut query = customer::table.into_boxed();
+
+    if let Some(name) = &filter.name {
+        query = query.filter(customer::name.ilike(contains_pattern(name)));
+    }
+    if let Some(phone) = &filter.phone {
+        query = query.filter(customer::phone.ilike(contains_pattern(phone)));
+    }
+    if let Some(address_id) = filter.address_id {
+        query = query.filter(customer::address_id.eq(address_id));
     }
+
+    query
 }
 
-#[get("/customer")]
-pub fn get_customers() -> Json<Vec<Customer>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+pub fn _get_customers(conn: &mut PgConnection, filter: &CustomerFilter, page: &PageParams, sort: &[SortKey]) -> QueryResult<Page<Customer>> {
+    let total = filter_customers(filter)
+        .count()
+        .get_result::<i64>(conn)?;
 
-    let customers = _get_customers(&mut conn);
+    let customers = sort_by!(filter_customers(filter), sort, {
+            "id" => customer::id,
+            "name" => customer::name,
+        })
+        .then_order_by(customer::id)
+        .limit(page.limit)
+        .offset(page.offset)
+        .load::<Customer>(conn)?;
 
-    Json(customers)
+    Ok(Page::new(customers, total, page))
 }
 
-pub fn _get_customers(conn: &mut PgConnection) -> Vec<Customer> {
-    customer::table
-        .load::<Customer>(conn)
-        .unwrap()
+#[derive(Debug, FromForm)]
+pub struct CustomerSearch {
+    pub q: String,
+}
+
+/// Trigram word similarity a name, street or neighborhood needs to match.
+const SEARCH_THRESHOLD: &str = "0.3";
+
+/// Fewest digits in a query for it to also be matched against phones.
+const MIN_PHONE_DIGITS: usize = 3;
+
+/// A customer matching a search, `score` being how well from 0 to 1.
+#[derive(Debug, Serialize)]
+pub struct CustomerMatch {
+    #[serde(flatten)]
+    pub customer: Customer,
+    pub score: f32,
+    pub last_order_date: Option<Date>,
+}
+
+#[derive(Debug, QueryableByName)]
+struct CustomerMatchRow {
+    #[diesel(sql_type = Int4)]
+    id: i32,
+    #[diesel(sql_type = Varchar)]
+    name: String,
+    #[diesel(sql_type = Nullable<Varchar>)]
+    phone: Option<String>,
+    #[diesel(sql_type = Int4)]
+    address_id: i32,
+    #[diesel(sql_type = Bool)]
+    phone_duplicate: bool,
+    #[diesel(sql_type = Float4)]
+    score: f32,
+    #[diesel(sql_type = Nullable<SqlDate>)]
+    last_order_date: Option<Date>,
+}
+
+#[derive(Debug, QueryableByName)]
+struct MatchCount {
+    #[diesel(sql_type = Int8)]
+    total: i64,
+}
+
+/// Customers whose name, phone, or the street or neighborhood of one of their
+/// addresses look like `q`. Street and neighborhood matches weigh less than
+/// name and phone ones.
+const CUSTOMER_MATCHES: &str = "
+    WITH matches AS (
+        SELECT c.id, greatest(
+            word_similarity($1, c.name),
+            CASE WHEN $2 <> '' AND c.phone LIKE '%' || $2 || '%' THEN 1 END,
+            max(word_similarity($1, a.street)) * 0.8,
+            max(word_similarity($1, n.name)) * 0.6
+        )::real AS score
+        FROM customer c
+        LEFT JOIN customer_address ca ON ca.customer_id = c.id
+        LEFT JOIN address a ON a.id = ca.address_id
+        LEFT JOIN neighborhood n ON n.id = a.neighborhood_id
+        WHERE $1 <% c.name
+           OR ($2 <> '' AND c.phone LIKE '%' || $2 || '%')
+           OR $1 <% a.street
+           OR $1 <% n.name
+        GROUP BY c.id
+    )
+";
+
+#[get("/customer/search?<query..>")]
+pub fn search_customers(mut conn: DbConn, query: ListQuery<CustomerSearch>) -> Result<Json<Page<CustomerMatch>>, ApiError> {
+    if query.page.sort.is_some() {
+        return Err(ApiError::BadRequest("Search results are ranked by relevance and can't be sorted".to_string()));
+    }
+    let q = query.filter.q.trim();
+    if q.chars().count() < 2 {
+        return Err(ApiError::Validation("Search for at least 2 characters".to_string(), Some(json!({ "q": q }))));
+    }
+
+    let customers = conn.transaction(|conn| _search_customers(conn, q, &query.page))?;
+
+    Ok(Json(customers))
+}
+
+/// Best matches first, customers who ordered more recently first among
+/// similarly good ones.
+pub fn _search_customers(conn: &mut PgConnection, q: &str, page: &PageParams) -> QueryResult<Page<CustomerMatch>> {
+    let digits = q.chars().filter(char::is_ascii_digit).collect::<String>();
+    let digits = if digits.len() >= MIN_PHONE_DIGITS { digits } else { String::new() };
+
+    // `<%` can use the trigram indexes but only takes its threshold from this setting
+    diesel::sql_query("SELECT set_config('pg_trgm.word_similarity_threshold', $1, true)")
+        .bind::<Text, _>(SEARCH_THRESHOLD)
+        .execute(conn)?;
+
+    let total = diesel::sql_query(format!("{} SELECT count(*) AS total FROM matches", CUSTOMER_MATCHES))
+        .bind::<Text, _>(q)
+        .bind::<Text, _>(&digits)
+        .get_result::<MatchCount>(conn)?
+        .total;
+
+    let customers = diesel::sql_query(format!("{}
+        SELECT c.id, c.name, c.phone, c.address_id, c.phone_duplicate, m.score, o.last_order_date
+        FROM matches m
+        JOIN customer c ON c.id = m.id
+        LEFT JOIN LATERAL (
+            SELECT max(date) AS last_order_date FROM customer_order WHERE customer_id = c.id
+        ) o ON true
+        ORDER BY round(m.score::numeric, 1) DESC, o.last_order_date DESC NULLS LAST, m.score DESC, c.id
+        LIMIT $3 OFFSET $4
+    ", CUSTOMER_MATCHES))
+        .bind::<Text, _>(q)
+        .bind::<Text, _>(&digits)
+        .bind::<Int8, _>(page.limit)
+        .bind::<Int8, _>(page.offset)
+        .load::<CustomerMatchRow>(conn)?
+        .into_iter()
+        .map(|row| CustomerMatch {
+            customer: Customer {
+                id: row.id,
+                name: row.name,
+                phone: row.phone,
+                address_id: row.address_id,
+                phone_duplicate: row.phone_duplicate,
+            },
+            score: row.score,
+            last_order_date: row.last_order_date,
+        })
+        .collect();
+
+    Ok(Page::new(customers, total, page))
 }
 
 #[put("/customer/<customer_id>", data = "<customer>")]
-pub fn update_customer(customer_id: i32, customer: Form<NewCustomer>) -> Result<Json<Customer>, (Status, String)> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-
-    let customer = _update_customer(&mut conn, customer_id, customer.into_inner());
-
-    match customer {
-        Ok(customer) => Ok(Json(customer)),
-        Err(err) => match err {
-            Error::NotFound => Err((Status::NotFound, "Customer not found".to_string())),
-            Error::DatabaseError(_, info) => Err((Status::InternalServerError, info.message().to_string())),
-            _ => Err((Status::InternalServerError, "Internal Server Error".to_string())),
+pub fn update_customer(mut conn: DbConn, config: &State<AppConfig>, customer_id: i32, customer: Payload<NewCustomer>) -> Result<Json<Customer>, ApiError> {
+    let mut customer = customer.into_inner();
+    customer.phone = normalize_phone(customer.phone, &config.store.phone)?;
+    let customer = conn.transaction(|conn| {
+        let current = _get_customer(conn, customer_id).or_not_found("Customer")?;
+        let phone_changed = customer.phone != current.phone;
+        if phone_changed {
+            ensure_phone_available(conn, customer.phone.as_deref(), Some(customer_id))?;
         }
-    }
+
+        _update_customer(conn, customer_id, customer, phone_changed).or_not_found("Customer")
+    })?;
+
+    Ok(Json(customer))
 }
 
-pub fn _update_customer(conn: &mut PgConnection, customer_id: i32, customer: NewCustomer) -> QueryResult<Customer> {
+/// A changed phone has been checked to be unique, so it clears `phone_duplicate`.
+pub fn _update_customer(conn: &mut PgConnection, customer_id: i32, customer: NewCustomer, phone_changed: bool) -> QueryResult<Customer> {
+    let phone_duplicate = phone_changed.then_some(customer::phone_duplicate.eq(false));
     diesel::update(customer::table.find(customer_id))
-        .set(customer)
+        .set((customer, phone_duplicate))
         .get_result::<Customer>(conn)
 }
 
-#[delete("/customer/<customer_id>")]
-pub fn delete_customer(customer_id: i32) -> Result<Status, (Status, String)> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-
-    let customer = _delete_customer(&mut conn, customer_id);
-
-    match customer {
-        Ok(_) => Ok(Status::Ok),
-        Err(err) => match err {
-            Error::NotFound => Err((Status::NotFound, "Customer not found".to_string())),
-            Error::DatabaseError(_, info) => Err((Status::InternalServerError, info.message().to_string())),
-            _ => Err((Status::InternalServerError, "Internal Server Error".to_string())),
+#[patch("/customer/<customer_id>", data = "<customer>")]
+pub fn patch_customer(mut conn: DbConn, config: &State<AppConfig>, customer_id: i32, customer: Payload<CustomerChangeset>) -> Result<Json<Customer>, ApiError> {
+    let mut customer = customer.into_inner();
+    let phone = customer.phone.take()
+        .map(|phone| normalize_phone(phone, &config.store.phone))
+        .transpose()?;
+    let customer = conn.transaction(|conn| {
+        let current = _get_customer(conn, customer_id).or_not_found("Customer")?;
+        // sending the phone the customer already has leaves it, flag included, alone
+        if let Some(phone) = phone.filter(|phone| *phone != current.phone) {
+            ensure_phone_available(conn, phone.as_deref(), Some(customer_id))?;
+            customer.phone = Some(phone);
         }
+
+        _patch_customer(conn, customer_id, customer).or_not_found("Customer")
+    })?;
+
+    Ok(Json(customer))
+}
+
+/// A new phone has been checked to be unique, so it clears `phone_duplicate`.
+pub fn _patch_customer(conn: &mut PgConnection, customer_id: i32, customer: CustomerChangeset) -> QueryResult<Customer> {
+    let phone_duplicate = customer.phone.as_ref().map(|_| customer::phone_duplicate.eq(false));
+    let updated = diesel::update(customer::table.find(customer_id))
+        .set((customer, phone_duplicate))
+        .get_result::<Customer>(conn)
+        .optional_empty_changeset()?;
+
+    match updated {
+        Some(customer) => Ok(customer),
+        None => _get_customer(conn, customer_id),
     }
 }
 
-pub fn _delete_customer(conn: &mut PgConnection, customer_id: i32) -> QueryResult<usize> {
+#[delete("/customer/<customer_id>")]
+pub fn delete_customer(mut conn: DbConn, customer_id: i32) -> Result<Json<Customer>, ApiError> {
+    let customer = _delete_customer(&mut conn, customer_id).or_not_found("Customer")?;
+
+    Ok(Json(customer))
+}
+
+pub fn _delete_customer(conn: &mut PgConnection, customer_id: i32) -> QueryResult<Customer> {
     diesel::delete(customer::table.find(customer_id))
-        .execute(conn)
+        .get_result::<Customer>(conn)
+}
+
+/// Normalizes a phone from a request body, an empty one meaning none.
+pub fn normalize_phone(phone: Option<String>, config: &PhoneConfig) -> Result<Option<String>, ApiError> {
+    match phone {
+        Some(phone) if !phone.trim().is_empty() => phone::normalize(&phone, config)
+            .map(Some)
+            .map_err(|message| ApiError::Validation(message, Some(json!({ "phone": phone })))),
+        _ => Ok(None),
+    }
+}
+
+/// A phone identifies one customer, `customer_id` being the one it's for.
+pub fn ensure_phone_available(conn: &mut PgConnection, phone: Option<&str>, customer_id: Option<i32>) -> Result<(), ApiError> {
+    let Some(phone) = phone else {
+        return Ok(());
+    };
+
+    let owner = _get_customer_by_phone(conn, phone).optional()?;
+    match owner {
+        Some(owner) if Some(owner.id) != customer_id => Err(ApiError::Conflict(
+            format!("Phone {} already belongs to customer {}", phone, owner.name),
+            Some(json!({ "phone": phone, "customer_id": owner.id })),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// A customer with what the operator confirms a delivery with.
+#[derive(Debug, Serialize)]
+pub struct CustomerLookup {
+    #[serde(flatten)]
+    pub customer: Customer,
+    pub addresses: Vec<CustomerAddressEntry>,
+    pub last_order: Option<FullOrder>,
+}
+
+#[get("/customer/by-phone/<phone>", rank = 2)]
+pub fn get_customer_by_phone(mut conn: DbConn, config: &State<AppConfig>, phone: &str) -> Result<Json<CustomerLookup>, ApiError> {
+    let phone = phone::normalize(phone, &config.store.phone)
+        .map_err(|message| ApiError::Validation(message, Some(json!({ "phone": phone }))))?;
+    let customer = _get_customer_by_phone(&mut conn, &phone).or_not_found("Customer")?;
+    let lookup = _get_customer_lookup(&mut conn, customer)?;
+
+    Ok(Json(lookup))
+}
+
+/// The customer owning a normalized phone. Flagged duplicates are left out.
+pub fn _get_customer_by_phone(conn: &mut PgConnection, phone: &str) -> QueryResult<Customer> {
+    customer::table
+        .filter(customer::phone.eq(phone))
+        .filter(customer::phone_duplicate.eq(false))
+        .first::<Customer>(conn)
+}
+
+pub fn _get_customer_lookup(conn: &mut PgConnection, customer: Customer) -> QueryResult<CustomerLookup> {
+    let addresses = _get_customer_addresses(conn, customer.id)?;
+
+    let last_order_id = customer_order::table
+        .filter(customer_order::customer_id.eq(customer.id))
+        .order((customer_order::date.desc(), customer_order::id.desc()))
+        .select(customer_order::id)
+        .first::<i32>(conn)
+        .optional()?;
+    let last_order = match last_order_id {
+        Some(order_id) => Some(_get_full_order(conn, order_id)?),
+        None => None,
+    };
+
+    Ok(CustomerLookup { customer, addresses, last_order })
+}
+
+/// Normalizes phones stored before they were, returning how many changed.
+/// One that collides with a customer already holding the normalized phone is
+/// flagged as a duplicate, one that can't be normalized is left as is.
+pub fn _normalize_stored_phones(conn: &mut PgConnection, config: &PhoneConfig) -> QueryResult<usize> {
+    let customers = customer::table
+        .filter(customer::phone.is_not_null())
+        .order(customer::id)
+        .load::<Customer>(conn)?;
+
+    let mut changed = 0;
+    for customer in customers {
+        let Some(stored) = customer.phone.as_deref().filter(|phone| !phone::is_canonical(phone)) else {
+            continue;
+        };
+        let phone = match phone::normalize(stored, config) {
+            Ok(phone) => phone,
+            Err(message) => {
+                warn!("Customer {} keeps its phone: {}", customer.id, message);
+                continue;
+            }
+        };
+
+        conn.transaction(|conn| {
+            let taken = _get_customer_by_phone(conn, &phone)
+                .optional()?
+                .is_some_and(|owner| owner.id != customer.id);
+
+            diesel::update(customer::table.find(customer.id))
+                .set((customer::phone.eq(&phone), customer::phone_duplicate.eq(customer.phone_duplicate || taken)))
+                .execute(conn)
+        })?;
+        changed += 1;
+    }
+
+    Ok(changed)
+}
+
+/// `_normalize_stored_phones` on a connection of its own, see `--normalize-phones`.
+pub fn normalize_stored_phones(pool: &DbPool, config: &PhoneConfig) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+
+    Ok(_normalize_stored_phones(&mut conn, config)?)
 }
@@ -1,54 +1,187 @@
-// use super::super::schema::address;
-// use super::super::schema::customer_address;
-// // use super::super::customer_address::dsl::*;
-// use diesel::prelude::*;
-// use rocket::form::{Form, FromForm};
-// use crate::{Address, DATABASE_URL};
-//
-//
-// #[derive(Debug, Queryable, Insertable, FromForm)]
-// #[table_name="customer_address"]
-// pub struct CustomerAddress {
-//     pub customer_id: i32,
-//     pub address_id: i32,
-// }
-//
-//
-// #[get("/customer_address/<customer_id>")]
-// pub fn get_customer_addresses(customer_id: i32) -> String {
-//     let mut conn = PgConnection::establish(DATABASE_URL)
-//         .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-//
-//     let addresses = get_customer_addresses(&mut conn, customer_id);
-//
-//     format!("{:?}", addresses)
-// }
-//
-// fn _get_customer_addresses(conn: &mut PgConnection, customer_id: i32) -> Vec<Address> {
-//     let customer_addresses_id = customer_address::table
-//         .filter(customer_address::customer_id.eq(&customer_id))
-//         .load::<CustomerAddress>(conn)
-//         .optional()
-//         .unwrap()
-//         .unwrap()
-//         .iter()
-//         .map(|customer_address| customer_address.address_id)
-//         .collect::<Vec<i32>>();
-//
-//     crate::get_addresses(conn, customer_addresses_id)
-// }
-//
-// #[post("/customer_address", data = "<customer_address>")]
-// pub fn create_customer_address(customer_address: Form<CustomerAddress>) -> String {
-//     let mut conn = PgConnection::establish(DATABASE_URL)
-//         .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-//
-//     let new_customer_address = diesel::insert_into(customer_address::table)
-//         .values(customer_address.into_inner())
-//         .get_result::<CustomerAddress>(&mut conn);
-//
-//     match new_customer_address {
-//         Ok(customer_address) => format!("{:?}", customer_address),
-//         Err(e) => format!("Error creating customer address: {}", e),
-//     }
-// }
+use crate::schema::{address, customer, customer_address};
+use diesel::prelude::*;
+use rocket::form::FromForm;
+use rocket::serde::json::{json, Json};
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::libs::address::{Address, NewAddress, _create_address, _get_address};
+use crate::libs::customer::{Customer, _get_customer};
+use crate::libs::neighborhood::_get_neighborhood;
+use crate::payload::Payload;
+use serde::{Deserialize, Serialize};
+
+
+#[derive(Debug, Queryable, Insertable, Serialize)]
+#[diesel(table_name = customer_address)]
+pub struct CustomerAddress {
+    pub customer_id: i32,
+    pub address_id: i32,
+}
+
+/// One of a customer's addresses. The default one is `customer.address_id`.
+#[derive(Debug, Serialize)]
+pub struct CustomerAddressEntry {
+    #[serde(flatten)]
+    pub address: Address,
+    pub is_default: bool,
+}
+
+/// Body of `POST /customer/<id>/addresses`: an existing address (`address_id`)
+/// or a new one (`address`), optionally made the default.
+#[derive(Debug, FromForm, Deserialize)]
+pub struct NewCustomerAddress {
+    pub address_id: Option<i32>,
+    pub address: Option<NewAddress>,
+    #[field(default = false)]
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+#[get("/customer/<customer_id>/addresses")]
+pub fn get_customer_addresses(mut conn: DbConn, customer_id: i32) -> Result<Json<Vec<CustomerAddressEntry>>, ApiError> {
+    _get_customer(&mut conn, customer_id).or_not_found("Customer")?;
+    let addresses = _get_customer_addresses(&mut conn, customer_id)?;
+
+    Ok(Json(addresses))
+}
+
+/// The customer's addresses, default first.
+pub fn _get_customer_addresses(conn: &mut PgConnection, customer_id: i32) -> QueryResult<Vec<CustomerAddressEntry>> {
+    let default_id = _get_customer(conn, customer_id)?.address_id;
+
+    let mut addresses = customer_address::table
+        .inner_join(address::table)
+        .filter(customer_address::customer_id.eq(customer_id))
+        .select(address::all_columns)
+        .order(address::id)
+        .load::<Address>(conn)?
+        .into_iter()
+        .map(|address| CustomerAddressEntry { is_default: address.id == default_id, address })
+        .collect::<Vec<_>>();
+    addresses.sort_by_key(|entry| !entry.is_default);
+
+    Ok(addresses)
+}
+
+#[post("/customer/<customer_id>/addresses", data = "<address>")]
+pub fn add_customer_address(mut conn: DbConn, customer_id: i32, address: Payload<NewCustomerAddress>) -> Result<Json<Vec<CustomerAddressEntry>>, ApiError> {
+    let addresses = conn.transaction(|conn| {
+        _get_customer(conn, customer_id).or_not_found("Customer")?;
+        _add_customer_address(conn, customer_id, address.into_inner())?;
+        _get_customer_addresses(conn, customer_id).map_err(ApiError::from)
+    })?;
+
+    Ok(Json(addresses))
+}
+
+/// Links an existing address or creates and links a new one, returning its id.
+pub fn _add_customer_address(conn: &mut PgConnection, customer_id: i32, address: NewCustomerAddress) -> Result<i32, ApiError> {
+    let address_id = match (address.address_id, address.address) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::Validation("Send either `address_id` or `address`, not both".to_string(), None));
+        }
+        (Some(address_id), None) => _get_address(conn, address_id)
+            .optional()?
+            .ok_or_else(|| ApiError::Validation(
+                format!("Address {} does not exist", address_id),
+                Some(json!({ "address_id": address_id })),
+            ))?
+            .id,
+        (None, Some(mut new_address)) => {
+            if new_address.delivery_fee.is_none() {
+                new_address.delivery_fee = Some(_get_neighborhood(conn, new_address.neighborhood_id)
+                    .or_not_found("Neighborhood")?
+                    .delivery_fee);
+            }
+            _create_address(conn, new_address)?.id
+        }
+        (None, None) => {
+            return Err(ApiError::Validation("Send either `address_id` or `address`".to_string(), None));
+        }
+    };
+
+    _link_customer_address(conn, customer_id, address_id)?;
+
+    if address.is_default {
+        _set_default_customer_address(conn, customer_id, address_id)?;
+    }
+
+    Ok(address_id)
+}
+
+pub fn _link_customer_address(conn: &mut PgConnection, customer_id: i32, address_id: i32) -> QueryResult<usize> {
+    diesel::insert_into(customer_address::table)
+        .values(CustomerAddress { customer_id, address_id })
+        .on_conflict_do_nothing()
+        .execute(conn)
+}
+
+#[put("/customer/<customer_id>/addresses/<address_id>/default")]
+pub fn set_default_customer_address(mut conn: DbConn, customer_id: i32, address_id: i32) -> Result<Json<Vec<CustomerAddressEntry>>, ApiError> {
+    let addresses = conn.transaction(|conn| {
+        _get_customer(conn, customer_id).or_not_found("Customer")?;
+        _get_customer_address(conn, customer_id, address_id).or_not_found("Customer address")?;
+        _set_default_customer_address(conn, customer_id, address_id)?;
+        _get_customer_addresses(conn, customer_id).map_err(ApiError::from)
+    })?;
+
+    Ok(Json(addresses))
+}
+
+pub fn _get_customer_address(conn: &mut PgConnection, customer_id: i32, address_id: i32) -> QueryResult<CustomerAddress> {
+    customer_address::table
+        .find((customer_id, address_id))
+        .first::<CustomerAddress>(conn)
+}
+
+fn _set_default_customer_address(conn: &mut PgConnection, customer_id: i32, address_id: i32) -> QueryResult<usize> {
+    diesel::update(customer::table.find(customer_id))
+        .set(customer::address_id.eq(address_id))
+        .execute(conn)
+}
+
+#[delete("/customer/<customer_id>/addresses/<address_id>")]
+pub fn delete_customer_address(mut conn: DbConn, customer_id: i32, address_id: i32) -> Result<Json<Vec<CustomerAddressEntry>>, ApiError> {
+    let addresses = conn.transaction(|conn| {
+        _delete_customer_address(conn, customer_id, address_id)?;
+        _get_customer_addresses(conn, customer_id).map_err(ApiError::from)
+    })?;
+
+    Ok(Json(addresses))
+}
+
+/// Unlinks the address from the customer, the address itself stays for the
+/// orders delivered to it. The default address can't be removed, set another
+/// default first.
+pub fn _delete_customer_address(conn: &mut PgConnection, customer_id: i32, address_id: i32) -> Result<(), ApiError> {
+    let customer = customer::table
+        .find(customer_id)
+        .for_update()
+        .first::<Customer>(conn)
+        .or_not_found("Customer")?;
+
+    _get_customer_address(conn, customer_id, address_id).or_not_found("Customer address")?;
+
+    if customer.address_id == address_id {
+        return Err(ApiError::Conflict(
+            "The default address can't be removed, set another default first".to_string(),
+            Some(json!({ "address_id": address_id })),
+        ));
+    }
+
+    diesel::delete(customer_address::table.find((customer_id, address_id)))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Orders can only be delivered to one of their customer's addresses.
+pub fn ensure_customer_address(conn: &mut PgConnection, customer_id: i32, address_id: i32) -> Result<(), ApiError> {
+    match _get_customer_address(conn, customer_id, address_id).optional()? {
+        Some(_) => Ok(()),
+        None => Err(ApiError::Validation(
+            format!("Address {} is not one of customer {}'s addresses", address_id, customer_id),
+            Some(json!({ "customer_id": customer_id, "address_id": address_id })),
+        )),
+    }
+}
@@ -0,0 +1,148 @@
+use diesel::prelude::*;
+use diesel::sql_types::{Date as SqlDate, Int4, Int8, Nullable, Numeric, SmallInt, Varchar};
+use rocket::serde::json::Json;
+use rocket::time::Date;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::libs::address::{Address, _get_address};
+use crate::libs::customer::_get_customer;
+use crate::libs::customer_order::{CustomerOrder, OrderFilter, ORDER_SORT_FIELDS, _get_orders};
+use crate::libs::order_status::OrderStatus;
+use crate::money::Money;
+use crate::pagination::{parse_sort, ListQuery, Page};
+use serde::Serialize;
+
+
+/// Items listed as a customer's favourites.
+const FAVOURITE_ITEMS: i64 = 5;
+
+/// A customer's lifetime figures. Cancelled orders only count in
+/// `cancelled_orders`.
+#[derive(Debug, Serialize)]
+pub struct CustomerStats {
+    pub customer_id: i32,
+    pub first_order_date: Option<Date>,
+    pub last_order_date: Option<Date>,
+    pub order_count: i64,
+    pub cancelled_orders: i64,
+    pub total_spent: Money,
+    pub average_ticket: Option<Money>,
+    pub favourite_items: Vec<FavouriteItem>,
+    pub usual_address: Option<Address>,
+}
+
+#[derive(Debug, QueryableByName)]
+struct OrderTotals {
+    #[diesel(sql_type = Nullable<SqlDate>)]
+    first_order_date: Option<Date>,
+    #[diesel(sql_type = Nullable<SqlDate>)]
+    last_order_date: Option<Date>,
+    #[diesel(sql_type = Int8)]
+    order_count: i64,
+    #[diesel(sql_type = Int8)]
+    cancelled_orders: i64,
+    #[diesel(sql_type = Numeric)]
+    total_spent: Money,
+    #[diesel(sql_type = Nullable<Numeric>)]
+    average_ticket: Option<Money>,
+}
+
+/// An item the customer orders often, named as on their latest order of it.
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct FavouriteItem {
+    #[diesel(sql_type = Int4)]
+    pub item_id: i32,
+    #[diesel(sql_type = Varchar)]
+    pub item_name: String,
+    #[diesel(sql_type = Int8)]
+    pub orders: i64,
+    #[diesel(sql_type = Int8)]
+    pub quantity: i64,
+}
+
+#[derive(Debug, QueryableByName)]
+struct UsualAddress {
+    #[diesel(sql_type = Int4)]
+    address_id: i32,
+}
+
+#[get("/customer/<customer_id>/orders?<query..>")]
+pub fn get_customer_orders(mut conn: DbConn, customer_id: i32, mut query: ListQuery<OrderFilter>) -> Result<Json<Page<CustomerOrder>>, ApiError> {
+    _get_customer(&mut conn, customer_id).or_not_found("Customer")?;
+
+    // latest first unless asked otherwise
+    let sort = parse_sort(query.page.sort.as_deref().or(Some("-date,-id")), ORDER_SORT_FIELDS)?;
+    query.filter.customer_id = Some(customer_id);
+    let orders = _get_orders(&mut conn, &query.filter, &query.page, &sort)?;
+
+    Ok(Json(orders))
+}
+
+#[get("/customer/<customer_id>/stats")]
+pub fn get_customer_stats(mut conn: DbConn, customer_id: i32) -> Result<Json<CustomerStats>, ApiError> {
+    _get_customer(&mut conn, customer_id).or_not_found("Customer")?;
+    let stats = _get_customer_stats(&mut conn, customer_id)?;
+
+    Ok(Json(stats))
+}
+
+pub fn _get_customer_stats(conn: &mut PgConnection, customer_id: i32) -> QueryResult<CustomerStats> {
+    let totals = diesel::sql_query("
+        SELECT min(date) FILTER (WHERE status <> $2) AS first_order_date,
+               max(date) FILTER (WHERE status <> $2) AS last_order_date,
+               count(*) FILTER (WHERE status <> $2) AS order_count,
+               count(*) FILTER (WHERE status = $2) AS cancelled_orders,
+               COALESCE(sum(grand_total) FILTER (WHERE status <> $2), 0) AS total_spent,
+               round(avg(grand_total) FILTER (WHERE status <> $2), 2) AS average_ticket
+        FROM customer_order
+        WHERE customer_id = $1
+    ")
+        .bind::<Int4, _>(customer_id)
+        .bind::<SmallInt, _>(OrderStatus::Cancelled)
+        .get_result::<OrderTotals>(conn)?;
+
+    let favourite_items = diesel::sql_query("
+        SELECT d.item_id, (array_agg(d.item_name ORDER BY o.date DESC, d.id DESC))[1] AS item_name,
+               count(DISTINCT d.order_id) AS orders, sum(d.quantity)::int8 AS quantity
+        FROM order_details d
+        JOIN customer_order o ON o.id = d.order_id
+        WHERE o.customer_id = $1 AND o.status <> $2
+        GROUP BY d.item_id
+        ORDER BY orders DESC, quantity DESC, max(o.date) DESC, d.item_id
+        LIMIT $3
+    ")
+        .bind::<Int4, _>(customer_id)
+        .bind::<SmallInt, _>(OrderStatus::Cancelled)
+        .bind::<Int8, _>(FAVOURITE_ITEMS)
+        .load::<FavouriteItem>(conn)?;
+
+    // the address delivered to most often, the most recent one on a tie
+    let usual_address_id = diesel::sql_query("
+        SELECT address_id
+        FROM customer_order
+        WHERE customer_id = $1 AND status <> $2 AND address_id IS NOT NULL
+        GROUP BY address_id
+        ORDER BY count(*) DESC, max(date) DESC, max(id) DESC
+        LIMIT 1
+    ")
+        .bind::<Int4, _>(customer_id)
+        .bind::<SmallInt, _>(OrderStatus::Cancelled)
+        .get_result::<UsualAddress>(conn)
+        .optional()?;
+    let usual_address = match usual_address_id {
+        Some(usual) => Some(_get_address(conn, usual.address_id)?),
+        None => None,
+    };
+
+    Ok(CustomerStats {
+        customer_id,
+        first_order_date: totals.first_order_date,
+        last_order_date: totals.last_order_date,
+        order_count: totals.order_count,
+        cancelled_orders: totals.cancelled_orders,
+        total_spent: totals.total_spent,
+        average_ticket: totals.average_ticket,
+        favourite_items,
+        usual_address,
+    })
+}
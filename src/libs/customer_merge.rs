@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use crate::schema::{customer, customer_address, customer_merge, customer_order};
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Float4, Int4, Int8, Text};
+use rocket::serde::json::{json, Json};
+use rocket::time::OffsetDateTime;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::libs::customer::{Customer, _get_customer};
+use crate::libs::customer_address::CustomerAddress;
+use crate::pagination::{Page, PageParams};
+use serde::Serialize;
+
+
+/// A merge of `merged_customer_id` into `customer_id`, with what the merged
+/// customer was and what moved off it.
+#[derive(Debug, Queryable, Serialize)]
+pub struct CustomerMerge {
+    pub id: i32,
+    pub customer_id: Option<i32>,
+    pub merged_customer_id: i32,
+    pub merged_name: String,
+    pub merged_phone: Option<String>,
+    pub merged_address_id: i32,
+    pub order_ids: Vec<i32>,
+    /// Addresses the surviving customer didn't have yet.
+    pub address_ids: Vec<i32>,
+    pub merged_at: OffsetDateTime,
+}
+
+/// Two customers that are likely the same person, `customer` being the older.
+#[derive(Debug, Serialize)]
+pub struct DuplicateCandidate {
+    pub customer: Customer,
+    pub other: Customer,
+    pub same_phone: bool,
+    pub shared_address: bool,
+    pub name_similarity: f32,
+}
+
+#[derive(Debug, QueryableByName)]
+struct DuplicatePair {
+    #[diesel(sql_type = Int4)]
+    customer_id: i32,
+    #[diesel(sql_type = Int4)]
+    other_id: i32,
+    #[diesel(sql_type = Bool)]
+    same_phone: bool,
+    #[diesel(sql_type = Bool)]
+    shared_address: bool,
+    #[diesel(sql_type = Float4)]
+    name_similarity: f32,
+}
+
+#[derive(Debug, QueryableByName)]
+struct PairCount {
+    #[diesel(sql_type = Int8)]
+    total: i64,
+}
+
+/// Trigram similarity two names need to be reported without a shared phone.
+const NAME_SIMILARITY: &str = "0.45";
+
+/// Pairs of customers with the same normalized phone or similar names.
+const DUPLICATE_PAIRS: &str = "
+    WITH pairs AS (
+        SELECT a.id AS customer_id, b.id AS other_id,
+            coalesce(a.phone = b.phone, false) AS same_phone,
+            EXISTS (
+                SELECT 1 FROM customer_address x
+                JOIN customer_address y ON y.address_id = x.address_id
+                WHERE x.customer_id = a.id AND y.customer_id = b.id
+            ) AS shared_address,
+            similarity(a.name, b.name)::real AS name_similarity
+        FROM customer a
+        JOIN customer b ON a.id < b.id AND (a.phone = b.phone OR a.name % b.name)
+    )
+";
+
+#[get("/customer/duplicates?<page..>")]
+pub fn get_duplicate_customers(mut conn: DbConn, page: PageParams) -> Result<Json<Page<DuplicateCandidate>>, ApiError> {
+    if page.sort.is_some() {
+        return Err(ApiError::BadRequest("Duplicate candidates are ranked by likeness and can't be sorted".to_string()));
+    }
+
+    let candidates = conn.transaction(|conn| _get_duplicate_customers(conn, &page))?;
+
+    Ok(Json(candidates))
+}
+
+/// Same phone first, then by how alike the names are.
+pub fn _get_duplicate_customers(conn: &mut PgConnection, page: &PageParams) -> QueryResult<Page<DuplicateCandidate>> {
+    // `%` can use the trigram index on names but only takes its threshold from this setting
+    diesel::sql_query("SELECT set_config('pg_trgm.similarity_threshold', $1, true)")
+        .bind::<Text, _>(NAME_SIMILARITY)
+        .execute(conn)?;
+
+    let total = diesel::sql_query(format!("{} SELECT count(*) AS total FROM pairs", DUPLICATE_PAIRS))
+        .get_result::<PairCount>(conn)?
+        .total;
+
+    let pairs = diesel::sql_query(format!("{}
+        SELECT * FROM pairs
+        ORDER BY same_phone DESC, name_similarity DESC, shared_address DESC, customer_id, other_id
+        LIMIT $1 OFFSET $2
+    ", DUPLICATE_PAIRS))
+        .bind::<Int8, _>(page.limit)
+        .bind::<Int8, _>(page.offset)
+        .load::<DuplicatePair>(conn)?;
+
+    let ids = pairs.iter()
+        .flat_map(|pair| [pair.customer_id, pair.other_id])
+        .collect::<Vec<_>>();
+    let customers = customer::table
+        .filter(customer::id.eq_any(&ids))
+        .load::<Customer>(conn)?
+        .into_iter()
+        .map(|customer| (customer.id, customer))
+        .collect::<HashMap<_, _>>();
+
+    let candidates = pairs.into_iter()
+        .filter_map(|pair| {
+            let customer = customers.get(&pair.customer_id).cloned()?;
+            let other = customers.get(&pair.other_id).cloned()?;
+
+            Some(DuplicateCandidate {
+                customer,
+                other,
+                same_phone: pair.same_phone,
+                shared_address: pair.shared_address,
+                name_similarity: pair.name_similarity,
+            })
+        })
+        .collect();
+
+    Ok(Page::new(candidates, total, page))
+}
+
+#[post("/customer/<customer_id>/merge/<other_id>")]
+pub fn merge_customers(mut conn: DbConn, customer_id: i32, other_id: i32) -> Result<Json<CustomerMerge>, ApiError> {
+    let merge = conn.transaction(|conn| _merge_customers(conn, customer_id, other_id))?;
+
+    Ok(Json(merge))
+}
+
+/// Moves the orders and addresses of `other_id` onto `customer_id` and deletes
+/// it. The surviving customer keeps its name, default address and phone, and
+/// takes the merged one's phone if it had none. Must run inside a transaction.
+pub fn _merge_customers(conn: &mut PgConnection, customer_id: i32, other_id: i32) -> Result<CustomerMerge, ApiError> {
+    if customer_id == other_id {
+        return Err(ApiError::Validation(
+            "A customer can't be merged into itself".to_string(),
+            Some(json!({ "customer_id": customer_id })),
+        ));
+    }
+
+    let mut locked = customer::table
+        .filter(customer::id.eq_any([customer_id, other_id]))
+        .order(customer::id)
+        .for_update()
+        .load::<Customer>(conn)?;
+    let other = locked.iter()
+        .position(|customer| customer.id == other_id)
+        .map(|index| locked.remove(index))
+        .ok_or_else(|| ApiError::not_found("Customer"))?;
+    let survivor = locked.pop()
+        .ok_or_else(|| ApiError::not_found("Customer"))?;
+
+    let mut order_ids = diesel::update(customer_order::table.filter(customer_order::customer_id.eq(other.id)))
+        .set(customer_order::customer_id.eq(survivor.id))
+        .returning(customer_order::id)
+        .get_results::<i32>(conn)?;
+    order_ids.sort_unstable();
+
+    let links = customer_address::table
+        .filter(customer_address::customer_id.eq(other.id))
+        .select(customer_address::address_id)
+        .load::<i32>(conn)?
+        .into_iter()
+        .map(|address_id| CustomerAddress { customer_id: survivor.id, address_id })
+        .collect::<Vec<_>>();
+    let mut address_ids = diesel::insert_into(customer_address::table)
+        .values(&links)
+        .on_conflict_do_nothing()
+        .returning(customer_address::address_id)
+        .get_results::<i32>(conn)?;
+    address_ids.sort_unstable();
+
+    // customers merged into the merged one earlier now belong to the survivor's history
+    diesel::update(customer_merge::table.filter(customer_merge::customer_id.eq(other.id)))
+        .set(customer_merge::customer_id.eq(survivor.id))
+        .execute(conn)?;
+
+    diesel::delete(customer::table.find(other.id))
+        .execute(conn)?;
+
+    // with the merged customer gone the survivor may now hold its phone alone
+    let phone = survivor.phone.clone().or(other.phone.clone());
+    let phone_duplicate = match &phone {
+        Some(phone) => diesel::select(diesel::dsl::exists(customer::table
+            .filter(customer::phone.eq(phone))
+            .filter(customer::phone_duplicate.eq(false))
+            .filter(customer::id.ne(survivor.id))))
+            .get_result::<bool>(conn)?,
+        None => false,
+    };
+    diesel::update(customer::table.find(survivor.id))
+        .set((customer::phone.eq(&phone), customer::phone_duplicate.eq(phone_duplicate)))
+        .execute(conn)?;
+
+    let merge = diesel::insert_into(customer_merge::table)
+        .values((
+            customer_merge::customer_id.eq(survivor.id),
+            customer_merge::merged_customer_id.eq(other.id),
+            customer_merge::merged_name.eq(&other.name),
+            customer_merge::merged_phone.eq(&other.phone),
+            customer_merge::merged_address_id.eq(other.address_id),
+            customer_merge::order_ids.eq(&order_ids),
+            customer_merge::address_ids.eq(&address_ids),
+        ))
+        .get_result::<CustomerMerge>(conn)?;
+
+    Ok(merge)
+}
+
+#[get("/customer/<customer_id>/merges")]
+pub fn get_customer_merges(mut conn: DbConn, customer_id: i32) -> Result<Json<Vec<CustomerMerge>>, ApiError> {
+    _get_customer(&mut conn, customer_id).or_not_found("Customer")?;
+    let merges = _get_customer_merges(&mut conn, customer_id)?;
+
+    Ok(Json(merges))
+}
+
+/// The customers merged into this one, latest first.
+pub fn _get_customer_merges(conn: &mut PgConnection, customer_id: i32) -> QueryResult<Vec<CustomerMerge>> {
+    customer_merge::table
+        .filter(customer_merge::customer_id.eq(customer_id))
+        .order((customer_merge::merged_at.desc(), customer_merge::id.desc()))
+        .load::<CustomerMerge>(conn)
+}
@@ -1,11 +1,19 @@
 use crate::schema::customer_order;
+use diesel::pg::Pg;
 use diesel::prelude::*;
-use diesel::result::Error;
-use rocket::serde::json::Json;
-use rocket::form::{Form, FromForm};
-use rocket::http::Status;
-use crate::DATABASE_URL;
-use serde::Serialize;
+use diesel::result::OptionalEmptyChangesetExtension;
+use rocket::serde::json::{json, Json};
+use rocket::form::FromForm;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::money::Money;
+use crate::percent::Percent;
+use crate::libs::customer_address::ensure_customer_address;
+use crate::libs::order_status::OrderStatus;
+use crate::libs::sales_channel::ensure_active_sales_channel;
+use crate::pagination::{parse_sort, sort_by, ListQuery, Page, PageParams, SortKey};
+use crate::payload::{double_option, Payload};
+use serde::{Deserialize, Serialize};
 use rocket::time::Date;
 
 
@@ -16,96 +24,187 @@ pub struct CustomerOrder {
     pub customer_id: i32,
     pub motoboy_id: Option<i32>,
     pub address_id: Option<i32>,
-    pub source: i16, // change to platform
-    pub additional: f64,
-    pub delivery_fee: f64,
-    pub discount: f64,
-    pub status: i16,
+    pub additional: Money,
+    pub delivery_fee: Money,
+    pub discount: Money,
+    pub status: OrderStatus,
+    pub sales_channel_id: i32,
+    /// Sum of the order's lines, maintained by a trigger on `order_details`.
+    pub subtotal: Money,
+    /// `subtotal + additional + delivery_fee - discount`, a generated column.
+    pub grand_total: Money,
+    /// The channel's commission when the order was taken, what revenue reports use.
+    pub commission_percent: Percent,
 }
 
-#[derive(Debug, AsChangeset, Insertable, FromForm)]
+#[derive(Debug, AsChangeset, Insertable, FromForm, Deserialize)]
 #[diesel(table_name = customer_order)]
 pub struct NewCustomerOrder {
     pub date: Date,
     pub customer_id: i32,
     pub motoboy_id: Option<i32>,
     pub address_id: Option<i32>,
-    pub source: i16, // change to platform
-    pub additional: f64,
-    pub delivery_fee: f64,
-    pub discount: f64,
-    pub status: i16,
+    pub sales_channel_id: i32,
+    pub additional: Money,
+    pub delivery_fee: Money,
+    pub discount: Money,
 }
 
-#[get("/order/<order_id>")]
-pub fn get_order(order_id: i32) -> Result<Json<CustomerOrder>, Status> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-
-    let address = _get_order(&mut conn, order_id);
+#[derive(Debug, AsChangeset, FromForm, Deserialize)]
+#[diesel(table_name = customer_order)]
+pub struct CustomerOrderChangeset {
+    pub date: Option<Date>,
+    pub customer_id: Option<i32>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub motoboy_id: Option<Option<i32>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub address_id: Option<Option<i32>>,
+    pub sales_channel_id: Option<i32>,
+    pub additional: Option<Money>,
+    pub delivery_fee: Option<Money>,
+    pub discount: Option<Money>,
+}
 
-    match address {
-        Ok(address) => Ok(Json(address)),
-        Err(err) => match err {
-            Error::NotFound => Err(Status::NotFound),
-            _ => Err(Status::InternalServerError),
-        }
+/// The discount can't take an order's grand total below zero.
+pub fn ensure_discount_fits(subtotal: Money, additional: Money, delivery_fee: Money, discount: Money) -> Result<(), ApiError> {
+    let max_discount = subtotal.checked_add(additional)
+        .and_then(|total| total.checked_add(delivery_fee))
+        .ok_or_else(|| ApiError::Validation(
+            "The order's total is too large".to_string(),
+            Some(json!({ "subtotal": subtotal, "additional": additional, "delivery_fee": delivery_fee })),
+        ))?;
+    if discount > max_discount {
+        return Err(ApiError::Validation(
+            format!("The discount of {} is more than the order's {} before discount", discount, max_discount),
+            Some(json!({ "discount": discount, "max_discount": max_discount })),
+        ));
     }
+
+    Ok(())
+}
+
+/// `ensure_discount_fits` on the order as stored, for changes to its lines.
+pub fn ensure_order_discount_fits(conn: &mut PgConnection, order_id: i32) -> Result<(), ApiError> {
+    let order = _get_order(conn, order_id).or_not_found("Order")?;
+
+    ensure_discount_fits(order.subtotal, order.additional, order.delivery_fee, order.discount)
+}
+
+#[get("/order/<order_id>")]
+pub fn get_order(mut conn: DbConn, order_id: i32) -> Result<Json<CustomerOrder>, ApiError> {
+    let order = _get_order(&mut conn, order_id).or_not_found("Order")?;
+
+    Ok(Json(order))
 }
 
-fn _get_order(conn: &mut PgConnection, order_id: i32) -> QueryResult<CustomerOrder> {
+pub fn _get_order(conn: &mut PgConnection, order_id: i32) -> QueryResult<CustomerOrder> {
     customer_order::table
         .find(order_id)
         .first::<CustomerOrder>(conn)
 }
 
-#[get("/order")]
-pub fn get_orders() -> Json<Vec<CustomerOrder>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-    let orders = _get_orders(&mut conn);
-    Json(orders)
+#[derive(Debug, FromForm)]
+pub struct OrderFilter {
+    pub from: Option<Date>,
+    pub to: Option<Date>,
+    pub status: Option<OrderStatus>,
+    pub customer_id: Option<i32>,
+    pub motoboy_id: Option<i32>,
+    pub sales_channel_id: Option<i32>,
 }
 
-fn _get_orders(conn: &mut PgConnection) -> Vec<CustomerOrder> {
-    customer_order::table
-        .load::<CustomerOrder>(conn)
-        .unwrap()
+pub const ORDER_SORT_FIELDS: &[&str] = &["id", "date", "customer_id", "motoboy_id", "status", "grand_total"];
+
+#[get("/order?<query..>")]
+pub fn get_orders(mut conn: DbConn, query: ListQuery<OrderFilter>) -> Result<Json<Page<CustomerOrder>>, ApiError> {
+    let sort = parse_sort(query.page.sort.as_deref(), ORDER_SORT_FIELDS)?;
+    let orders = _get_orders(&mut conn, &query.filter, &query.page, &sort)?;
+    Ok(Json(orders))
 }
 
-#[post("/order", data = "<order>")]
-pub fn create_order(order: Form<NewCustomerOrder>) -> Result<Json<CustomerOrder>, Status> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+fn filter_orders(filter: &OrderFilter) -> customer_order::BoxedQuery<'static, Pg> {
+    let mut query = customer_order::table.into_boxed();
+
+    if let Some(from) = filter.from {
+        query = query.filter(customer_order::date.ge(from));
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(customer_order::date.le(to));
+    }
+    if let Some(status) = filter.status {
+        query = query.filter(customer_order::status.eq(status));
+    }
+    if let Some(customer_id) = filter.customer_id {
+        query = query.filter(customer_order::customer_id.eq(customer_id));
+    }
+    if let Some(motoboy_id) = filter.motoboy_id {
+        query = query.filter(customer_order::motoboy_id.eq(motoboy_id));
+    }
+    if let Some(sales_channel_id) = filter.sales_channel_id {
+        query = query.filter(customer_order::sales_channel_id.eq(sales_channel_id));
+    }
+
+    query
+}
 
-    let new_order = _create_order(&mut conn, order.into_inner());
+pub fn _get_orders(conn: &mut PgConnection, filter: &OrderFilter, page: &PageParams, sort: &[SortKey]) -> QueryResult<Page<CustomerOrder>> {
+    let total = filter_orders(filter)
+        .count()
+        .get_result::<i64>(conn)?;
 
-    match new_order {
-        Ok(order) => Ok(Json(order)),
-        Err(err) => Err(Status::InternalServerError),
+    let orders = sort_by!(filter_orders(filter), sort, {
+            "id" => customer_order::id,
+            "date" => customer_order::date,
+            "customer_id" => customer_order::customer_id,
+            "motoboy_id" => customer_order::motoboy_id,
+            "status" => customer_order::status,
+            "grand_total" => customer_order::grand_total,
+        })
+        .then_order_by(customer_order::id)
+        .limit(page.limit)
+        .offset(page.offset)
+        .load::<CustomerOrder>(conn)?;
+
+    Ok(Page::new(orders, total, page))
+}
+
+/// The order starts without lines, so its discount can't be more than the
+/// additional and delivery fee yet. Larger discounts are set once the lines
+/// are in, or sent with them to `POST /order/full`.
+#[post("/order", data = "<order>")]
+pub fn create_order(mut conn: DbConn, order: Payload<NewCustomerOrder>) -> Result<Json<CustomerOrder>, ApiError> {
+    ensure_active_sales_channel(&mut conn, order.sales_channel_id)?;
+    ensure_discount_fits(Money::ZERO, order.additional, order.delivery_fee, order.discount)?;
+    if let Some(address_id) = order.address_id {
+        ensure_customer_address(&mut conn, order.customer_id, address_id)?;
     }
+    let new_order = _create_order(&mut conn, order.into_inner())?;
+
+    Ok(Json(new_order))
 }
 
-fn _create_order(conn: &mut PgConnection, order: NewCustomerOrder) -> QueryResult<CustomerOrder> {
+pub fn _create_order(conn: &mut PgConnection, order: NewCustomerOrder) -> QueryResult<CustomerOrder> {
     diesel::insert_into(customer_order::table)
         .values(order)
         .get_result::<CustomerOrder>(conn)
 }
 
 #[put("/order/<order_id>", data = "<order>")]
-pub fn update_order(order_id: i32, mut order: Form<NewCustomerOrder>) -> Result<Json<CustomerOrder>, Status> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-
-    let updated_order = _update_order(&mut conn, order_id, order.into_inner());
-
-    match updated_order {
-        Ok(order) => Ok(Json(order)),
-        Err(err) => match err {
-            Error::NotFound => Err(Status::NotFound),
-            _ => Err(Status::InternalServerError),
+pub fn update_order(mut conn: DbConn, order_id: i32, order: Payload<NewCustomerOrder>) -> Result<Json<CustomerOrder>, ApiError> {
+    let updated_order = conn.transaction(|conn| {
+        let current = _get_order(conn, order_id).or_not_found("Order")?;
+        // orders already on a channel that was later deactivated can still be edited
+        if order.sales_channel_id != current.sales_channel_id {
+            ensure_active_sales_channel(conn, order.sales_channel_id)?;
         }
-    }
+        if let Some(address_id) = order.address_id {
+            ensure_customer_address(conn, order.customer_id, address_id)?;
+        }
+        ensure_discount_fits(current.subtotal, order.additional, order.delivery_fee, order.discount)?;
+        _update_order(conn, order_id, order.into_inner()).map_err(ApiError::from)
+    })?;
+
+    Ok(Json(updated_order))
 }
 
 fn _update_order(conn: &mut PgConnection, order_id: i32, order: NewCustomerOrder) -> QueryResult<CustomerOrder> {
@@ -114,20 +213,70 @@ fn _update_order(conn: &mut PgConnection, order_id: i32, order: NewCustomerOrder
         .get_result::<CustomerOrder>(conn)
 }
 
-#[delete("/order/<order_id>")]
-pub fn delete_order(order_id: i32) -> String {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+#[patch("/order/<order_id>", data = "<order>")]
+pub fn patch_order(mut conn: DbConn, order_id: i32, order: Payload<CustomerOrderChangeset>) -> Result<Json<CustomerOrder>, ApiError> {
+    let updated_order = conn.transaction(|conn| {
+        let current = _get_order(conn, order_id).or_not_found("Order")?;
+        if let Some(sales_channel_id) = order.sales_channel_id.filter(|id| *id != current.sales_channel_id) {
+            ensure_active_sales_channel(conn, sales_channel_id)?;
+        }
+        if order.customer_id.is_some() || order.address_id.is_some() {
+            let customer_id = order.customer_id.unwrap_or(current.customer_id);
+            if let Some(address_id) = order.address_id.unwrap_or(current.address_id) {
+                ensure_customer_address(conn, customer_id, address_id)?;
+            }
+        }
+        ensure_discount_fits(
+            current.subtotal,
+            order.additional.unwrap_or(current.additional),
+            order.delivery_fee.unwrap_or(current.delivery_fee),
+            order.discount.unwrap_or(current.discount),
+        )?;
+        _patch_order(conn, order_id, order.into_inner()).map_err(ApiError::from)
+    })?;
 
-    let deleted_order = _delete_order(&mut conn, order_id);
+    Ok(Json(updated_order))
+}
+
+fn _patch_order(conn: &mut PgConnection, order_id: i32, order: CustomerOrderChangeset) -> QueryResult<CustomerOrder> {
+    let updated = diesel::update(customer_order::table.find(order_id))
+        .set(&order)
+        .get_result::<CustomerOrder>(conn)
+        .optional_empty_changeset()?;
 
-    match deleted_order {
-        Ok(_) => "Order deleted".to_string(),
-        Err(_) => "Error deleting order".to_string(),
+    match updated {
+        Some(order) => Ok(order),
+        None => _get_order(conn, order_id),
     }
 }
 
-fn _delete_order(conn: &mut PgConnection, order_id: i32) -> QueryResult<usize> {
-    diesel::delete(customer_order::table.find(order_id))
-        .execute(conn)
+#[delete("/order/<order_id>")]
+pub fn delete_order(mut conn: DbConn, order_id: i32) -> Result<Json<CustomerOrder>, ApiError> {
+    let deleted_order = conn.transaction(|conn| _delete_order(conn, order_id))?;
+
+    Ok(Json(deleted_order))
+}
+
+/// Only orders still waiting to be prepared can be deleted. Once preparing,
+/// their ingredients are out of stock and they belong to the history, so
+/// they're cancelled instead. Must run inside a transaction.
+fn _delete_order(conn: &mut PgConnection, order_id: i32) -> Result<CustomerOrder, ApiError> {
+    let status = customer_order::table
+        .find(order_id)
+        .select(customer_order::status)
+        .for_update()
+        .first::<OrderStatus>(conn)
+        .or_not_found("Order")?;
+
+    if status != OrderStatus::Received {
+        return Err(ApiError::Conflict(
+            format!("Cannot delete an order that is {}, only received orders can be deleted", status.as_str()),
+            Some(json!({ "status": status })),
+        ));
+    }
+
+    let order = diesel::delete(customer_order::table.find(order_id))
+        .get_result::<CustomerOrder>(conn)?;
+
+    Ok(order)
 }
@@ -0,0 +1,203 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use rocket::form::FromForm;
+use rocket::serde::json::{json, Json};
+use rocket::time::Date;
+use rocket::State;
+use crate::config::AppConfig;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::money::Money;
+use crate::libs::address::{Address, NewAddress, _create_address, _get_address};
+use crate::libs::customer::{Customer, NewCustomer, ensure_phone_available, normalize_phone, _create_customer, _get_customer};
+use crate::libs::customer_address::{ensure_customer_address, _link_customer_address};
+use crate::libs::customer_order::{CustomerOrder, NewCustomerOrder, ensure_order_discount_fits, _create_order, _get_order};
+use crate::libs::item::{OrderOverrides, _get_orderable_item};
+use crate::libs::neighborhood::_get_neighborhood;
+use crate::libs::order_details::{OrderLine, NewOrderLine, _create_order_details, _get_order_details, _resolve_line};
+use crate::libs::sales_channel::ensure_active_sales_channel;
+use crate::payload::Payload;
+use serde::{Deserialize, Serialize};
+
+
+/// An order with everything it references, as returned by `/order/full`.
+#[derive(Debug, Serialize)]
+pub struct FullOrder {
+    #[serde(flatten)]
+    pub order: CustomerOrder,
+    pub customer: Customer,
+    pub address: Option<Address>,
+    pub lines: Vec<OrderLine>,
+}
+
+#[derive(Debug, FromForm, Deserialize)]
+pub struct NewOrderCustomer {
+    pub name: String,
+    pub phone: Option<String>,
+}
+
+/// Body of `POST /order/full`. The customer is either an existing one
+/// (`customer_id`) or created on the fly (`customer`), likewise the address.
+/// `delivery_fee` defaults to the address's fee.
+#[derive(Debug, FromForm, Deserialize)]
+pub struct NewFullOrder {
+    pub date: Date,
+    pub customer_id: Option<i32>,
+    pub customer: Option<NewOrderCustomer>,
+    pub address_id: Option<i32>,
+    pub address: Option<NewAddress>,
+    pub motoboy_id: Option<i32>,
+    pub sales_channel_id: i32,
+    #[field(default = Money::ZERO)]
+    #[serde(default)]
+    pub additional: Money,
+    pub delivery_fee: Option<Money>,
+    #[field(default = Money::ZERO)]
+    #[serde(default)]
+    pub discount: Money,
+    pub lines: Vec<NewOrderLine>,
+}
+
+#[get("/order/<order_id>/full")]
+pub fn get_full_order(mut conn: DbConn, order_id: i32) -> Result<Json<FullOrder>, ApiError> {
+    let order = _get_full_order(&mut conn, order_id).or_not_found("Order")?;
+
+    Ok(Json(order))
+}
+
+pub fn _get_full_order(conn: &mut PgConnection, order_id: i32) -> QueryResult<FullOrder> {
+    let order = _get_order(conn, order_id)?;
+    let customer = _get_customer(conn, order.customer_id)?;
+    let address = match order.address_id {
+        Some(address_id) => Some(_get_address(conn, address_id)?),
+        None => None,
+    };
+    let lines = _get_order_details(conn, order_id)?;
+
+    Ok(FullOrder { order, customer, address, lines })
+}
+
+#[post("/order/full?<overrides..>", data = "<order>")]
+pub fn create_full_order(mut conn: DbConn, config: &State<AppConfig>, overrides: OrderOverrides, order: Payload<NewFullOrder>) -> Result<Json<FullOrder>, ApiError> {
+    let now = config.store.now();
+    let mut order = order.into_inner();
+    if let Some(customer) = &mut order.customer {
+        customer.phone = normalize_phone(customer.phone.take(), &config.store.phone)
+            .map_err(|err| err.with_context("customer", json!({})))?;
+    }
+
+    let order = conn.transaction(|conn| {
+        let order_id = _create_full_order(conn, order, overrides, &now)?;
+        _get_full_order(conn, order_id).map_err(ApiError::from)
+    })?;
+
+    Ok(Json(order))
+}
+
+fn missing(resource: &str, field: &str, id: i32) -> ApiError {
+    ApiError::Validation(format!("{} {} does not exist", resource, id), Some(json!({ field: id })))
+}
+
+/// Creates the customer and address if needed, then the order and its lines.
+/// Must run inside a transaction so a failing line leaves nothing behind.
+pub fn _create_full_order(conn: &mut PgConnection, order: NewFullOrder, overrides: OrderOverrides, now: &DateTime<Tz>) -> Result<i32, ApiError> {
+    if order.lines.is_empty() {
+        return Err(ApiError::Validation("An order needs at least one line".to_string(), None));
+    }
+
+    ensure_active_sales_channel(conn, order.sales_channel_id)?;
+
+    let address = match (order.address_id, order.address) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::Validation("Send either `address_id` or `address`, not both".to_string(), None));
+        }
+        (Some(address_id), None) => Some(_get_address(conn, address_id)
+            .optional()?
+            .ok_or_else(|| missing("Address", "address_id", address_id))?),
+        (None, Some(mut address)) => {
+            if address.delivery_fee.is_none() {
+                address.delivery_fee = Some(_get_neighborhood(conn, address.neighborhood_id)
+                    .optional()?
+                    .ok_or_else(|| missing("Neighborhood", "neighborhood_id", address.neighborhood_id))
+                    .map_err(|err| err.with_context("address", json!({})))?
+                    .delivery_fee);
+            }
+            Some(_create_address(conn, address).map_err(|err| ApiError::from(err).with_context("address", json!({})))?)
+        }
+        (None, None) => None,
+    };
+
+    let customer_id = match (order.customer_id, order.customer) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::Validation("Send either `customer_id` or `customer`, not both".to_string(), None));
+        }
+        (Some(customer_id), None) => {
+            let customer = _get_customer(conn, customer_id)
+                .optional()?
+                .ok_or_else(|| missing("Customer", "customer_id", customer_id))?;
+
+            // a new address becomes one of the customer's, an existing one must already be
+            match (&address, order.address_id) {
+                (Some(address), Some(_)) => ensure_customer_address(conn, customer.id, address.id)?,
+                (Some(address), None) => {
+                    _link_customer_address(conn, customer.id, address.id)?;
+                }
+                (None, _) => {}
+            }
+
+            customer.id
+        }
+        (None, Some(customer)) => {
+            let address_id = address.as_ref()
+                .map(|address| address.id)
+                .ok_or_else(|| ApiError::Validation("A new customer needs an address".to_string(), None))?;
+
+            ensure_phone_available(conn, customer.phone.as_deref(), None)
+                .map_err(|err| err.with_context("customer", json!({})))?;
+
+            _create_customer(conn, NewCustomer { name: customer.name, phone: customer.phone, address_id })
+                .map_err(|err| ApiError::from(err).with_context("customer", json!({})))?
+                .id
+        }
+        (None, None) => {
+            return Err(ApiError::Validation("Send either `customer_id` or `customer`".to_string(), None));
+        }
+    };
+
+    let delivery_fee = order.delivery_fee
+        .or(address.as_ref().map(|address| address.delivery_fee))
+        .unwrap_or(Money::ZERO);
+
+    let new_order = _create_order(conn, NewCustomerOrder {
+        date: order.date,
+        customer_id,
+        motoboy_id: order.motoboy_id,
+        address_id: address.as_ref().map(|address| address.id),
+        sales_channel_id: order.sales_channel_id,
+        additional: order.additional,
+        delivery_fee,
+        discount: order.discount,
+    })?;
+
+    for (index, line) in order.lines.into_iter().enumerate() {
+        let context = json!({ "line": index, "item_id": line.item_id });
+
+        let line_error = |err: ApiError| err.with_context(&format!("lines[{}]", index), context.clone());
+
+        let item = _get_orderable_item(conn, line.item_id, overrides, now)
+            .map_err(|err| match err {
+                ApiError::NotFound(_) => missing("Item", "item_id", line.item_id),
+                err => err,
+            })
+            .map_err(line_error)?;
+        let choices = _resolve_line(conn, item.id, &line.option_ids, &line.swaps, overrides, now).map_err(line_error)?;
+
+        _create_order_details(conn, new_order.id, &item, line.quantity, choices)
+            .map_err(|err| line_error(ApiError::from(err)))?;
+    }
+
+    ensure_order_discount_fits(conn, new_order.id)?;
+
+    Ok(new_order.id)
+}
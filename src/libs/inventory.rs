@@ -0,0 +1,457 @@
+use crate::schema::{ingredient, recipe_ingredient, stock_movement};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::prelude::*;
+use diesel::result::OptionalEmptyChangesetExtension;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::{Int4, SmallInt};
+use rocket::form::{FromForm, FromFormField};
+use rocket::serde::json::{json, Json};
+use rocket::time::OffsetDateTime;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::libs::item::_get_item;
+use crate::pagination::{contains_pattern, parse_sort, sort_by, ListQuery, Page, PageParams, SortKey};
+use crate::payload::Payload;
+use crate::quantity::Quantity;
+use serde::{Deserialize, Serialize};
+
+
+/// Why an ingredient's stock changed, stored as a checked smallint in
+/// `stock_movement.kind`. Consumption and cancellation are only recorded by
+/// order transitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, FromFormField, Serialize, Deserialize)]
+#[diesel(sql_type = SmallInt)]
+#[serde(rename_all = "snake_case")]
+pub enum StockMovementKind {
+    #[field(value = "purchase")]
+    Purchase,
+    #[field(value = "adjustment")]
+    Adjustment,
+    #[field(value = "waste")]
+    Waste,
+    #[field(value = "consumption")]
+    Consumption,
+    #[field(value = "cancellation")]
+    Cancellation,
+}
+
+impl StockMovementKind {
+    const ALL: [StockMovementKind; 5] = [
+        StockMovementKind::Purchase,
+        StockMovementKind::Adjustment,
+        StockMovementKind::Waste,
+        StockMovementKind::Consumption,
+        StockMovementKind::Cancellation,
+    ];
+
+    fn to_i16(self) -> i16 {
+        match self {
+            StockMovementKind::Purchase => 0,
+            StockMovementKind::Adjustment => 1,
+            StockMovementKind::Waste => 2,
+            StockMovementKind::Consumption => 3,
+            StockMovementKind::Cancellation => 4,
+        }
+    }
+}
+
+impl ToSql<SmallInt, Pg> for StockMovementKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <i16 as ToSql<SmallInt, Pg>>::to_sql(&self.to_i16(), &mut out.reborrow())
+    }
+}
+
+impl FromSql<SmallInt, Pg> for StockMovementKind {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let value = <i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)?;
+
+        StockMovementKind::ALL.into_iter()
+            .find(|kind| kind.to_i16() == value)
+            .ok_or_else(|| format!("Unrecognized stock movement kind {}", value).into())
+    }
+}
+
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct Ingredient {
+    pub id: i32,
+    pub name: String,
+    pub unit: String,
+    pub stock: Quantity,
+    pub reorder_level: Quantity,
+}
+
+/// Stock starts at zero and only changes through movements.
+#[derive(Debug, AsChangeset, Insertable, FromForm, Deserialize)]
+#[diesel(table_name = ingredient)]
+pub struct NewIngredient {
+    pub name: String,
+    pub unit: String,
+    #[field(default = Quantity::ZERO)]
+    #[serde(default)]
+    pub reorder_level: Quantity,
+}
+
+#[derive(Debug, AsChangeset, FromForm, Deserialize)]
+#[diesel(table_name = ingredient)]
+pub struct IngredientChangeset {
+    pub name: Option<String>,
+    pub unit: Option<String>,
+    pub reorder_level: Option<Quantity>,
+}
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct StockMovement {
+    pub id: i32,
+    pub ingredient_id: i32,
+    pub kind: StockMovementKind,
+    pub quantity: Quantity,
+    pub order_id: Option<i32>,
+    pub note: Option<String>,
+    pub created_at: OffsetDateTime,
+}
+
+/// A purchase or waste is a positive amount brought in or thrown away, an
+/// adjustment is the signed correction found when counting stock.
+#[derive(Debug, FromForm, Deserialize)]
+pub struct NewStockMovement {
+    pub kind: StockMovementKind,
+    pub quantity: Quantity,
+    pub note: Option<String>,
+}
+
+/// An ingredient line of an item's recipe, for one unit of the item.
+#[derive(Debug, Queryable, Serialize)]
+pub struct RecipeIngredient {
+    pub ingredient_id: i32,
+    pub name: String,
+    pub unit: String,
+    pub quantity: Quantity,
+}
+
+#[derive(Debug, FromForm, Deserialize)]
+pub struct NewRecipeIngredient {
+    pub ingredient_id: i32,
+    pub quantity: Quantity,
+}
+
+#[get("/ingredient/<ingredient_id>")]
+pub fn get_ingredient(mut conn: DbConn, ingredient_id: i32) -> Result<Json<Ingredient>, ApiError> {
+    let ingredient = _get_ingredient(&mut conn, ingredient_id).or_not_found("Ingredient")?;
+
+    Ok(Json(ingredient))
+}
+
+pub fn _get_ingredient(conn: &mut PgConnection, ingredient_id: i32) -> QueryResult<Ingredient> {
+    ingredient::table
+        .find(ingredient_id)
+        .first::<Ingredient>(conn)
+}
+
+#[post("/ingredient", data = "<ingredient>")]
+pub fn create_ingredient(mut conn: DbConn, ingredient: Payload<NewIngredient>) -> Result<Json<Ingredient>, ApiError> {
+    let ingredient = _create_ingredient(&mut conn, ingredient.into_inner())?;
+
+    Ok(Json(ingredient))
+}
+
+fn _create_ingredient(conn: &mut PgConnection, ingredient: NewIngredient) -> QueryResult<Ingredient> {
+    diesel::insert_into(ingredient::table)
+        .values(ingredient)
+        .get_result::<Ingredient>(conn)
+}
+
+#[put("/ingredient/<ingredient_id>", data = "<ingredient>")]
+pub fn update_ingredient(mut conn: DbConn, ingredient_id: i32, ingredient: Payload<NewIngredient>) -> Result<Json<Ingredient>, ApiError> {
+    let ingredient = _update_ingredient(&mut conn, ingredient_id, ingredient.into_inner()).or_not_found("Ingredient")?;
+
+    Ok(Json(ingredient))
+}
+
+fn _update_ingredient(conn: &mut PgConnection, ingredient_id: i32, ingredient: NewIngredient) -> QueryResult<Ingredient> {
+    diesel::update(ingredient::table.find(ingredient_id))
+        .set(ingredient)
+        .get_result::<Ingredient>(conn)
+}
+
+#[patch("/ingredient/<ingredient_id>", data = "<ingredient>")]
+pub fn patch_ingredient(mut conn: DbConn, ingredient_id: i32, ingredient: Payload<IngredientChangeset>) -> Result<Json<Ingredient>, ApiError> {
+    let ingredient = _patch_ingredient(&mut conn, ingredient_id, ingredient.into_inner()).or_not_found("Ingredient")?;
+
+    Ok(Json(ingredient))
+}
+
+fn _patch_ingredient(conn: &mut PgConnection, ingredient_id: i32, ingredient: IngredientChangeset) -> QueryResult<Ingredient> {
+    let updated = diesel::update(ingredient::table.find(ingredient_id))
+        .set(ingredient)
+        .get_result::<Ingredient>(conn)
+        .optional_empty_changeset()?;
+
+    match updated {
+        Some(ingredient) => Ok(ingredient),
+        None => _get_ingredient(conn, ingredient_id),
+    }
+}
+
+#[delete("/ingredient/<ingredient_id>")]
+pub fn delete_ingredient(mut conn: DbConn, ingredient_id: i32) -> Result<Json<Ingredient>, ApiError> {
+    let ingredient = _delete_ingredient(&mut conn, ingredient_id).or_not_found("Ingredient")?;
+
+    Ok(Json(ingredient))
+}
+
+fn _delete_ingredient(conn: &mut PgConnection, ingredient_id: i32) -> QueryResult<Ingredient> {
+    diesel::delete(ingredient::table.find(ingredient_id))
+        .get_result::<Ingredient>(conn)
+}
+
+#[derive(Debug, FromForm)]
+pub struct IngredientFilter {
+    pub name: Option<String>,
+}
+
+const INGREDIENT_SORT_FIELDS: &[&str] = &["id", "name", "stock", "reorder_level"];
+
+#[get("/ingredient?<query..>")]
+pub fn get_ingredients(mut conn: DbConn, query: ListQuery<IngredientFilter>) -> Result<Json<Page<Ingredient>>, ApiError> {
+    let sort = parse_sort(query.page.sort.as_deref(), INGREDIENT_SORT_FIELDS)?;
+    let ingredients = _get_ingredients(&mut conn, &query.filter, &query.page, &sort)?;
+
+    Ok(Json(ingredients))
+}
+
+fn filter_ingredients(filter: &IngredientFilter) -> ingredient::BoxedQuery<'static, Pg> {
+    let mut query = ingredient::table.into_boxed();
+
+    if let Some(name) = &filter.name {
+        query = query.filter(ingredient::name.ilike(contains_pattern(name)));
+    }
+
+    query
+}
+
+fn _get_ingredients(conn: &mut PgConnection, filter: &IngredientFilter, page: &PageParams, sort: &[SortKey]) -> QueryResult<Page<Ingredient>> {
+    let total = filter_ingredients(filter)
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let ingredients = sort_by!(filter_ingredients(filter), sort, {
+            "id" => ingredient::id,
+            "name" => ingredient::name,
+            "stock" => ingredient::stock,
+            "reorder_level" => ingredient::reorder_level,
+        })
+        .then_order_by(ingredient::id)
+        .limit(page.limit)
+        .offset(page.offset)
+        .load::<Ingredient>(conn)?;
+
+    Ok(Page::new(ingredients, total, page))
+}
+
+#[get("/ingredient/low_stock")]
+pub fn get_low_stock_ingredients(mut conn: DbConn) -> Result<Json<Vec<Ingredient>>, ApiError> {
+    let ingredients = _get_low_stock_ingredients(&mut conn)?;
+
+    Ok(Json(ingredients))
+}
+
+/// Ingredients whose stock is below their reorder level, the furthest below first.
+pub fn _get_low_stock_ingredients(conn: &mut PgConnection) -> QueryResult<Vec<Ingredient>> {
+    ingredient::table
+        .filter(ingredient::stock.lt(ingredient::reorder_level))
+        .order(((ingredient::reorder_level - ingredient::stock).desc(), ingredient::name))
+        .load::<Ingredient>(conn)
+}
+
+#[post("/ingredient/<ingredient_id>/movement", data = "<movement>")]
+pub fn create_stock_movement(mut conn: DbConn, ingredient_id: i32, movement: Payload<NewStockMovement>) -> Result<Json<StockMovement>, ApiError> {
+    _get_ingredient(&mut conn, ingredient_id).or_not_found("Ingredient")?;
+    let movement = _create_stock_movement(&mut conn, ingredient_id, movement.into_inner())?;
+
+    Ok(Json(movement))
+}
+
+pub fn _create_stock_movement(conn: &mut PgConnection, ingredient_id: i32, movement: NewStockMovement) -> Result<StockMovement, ApiError> {
+    let quantity = match movement.kind {
+        StockMovementKind::Purchase | StockMovementKind::Waste if movement.quantity <= Quantity::ZERO => {
+            return Err(ApiError::Validation(
+                "quantity must be positive for purchases and waste".to_string(),
+                Some(json!({ "quantity": movement.quantity })),
+            ));
+        }
+        StockMovementKind::Adjustment if movement.quantity == Quantity::ZERO => {
+            return Err(ApiError::Validation("An adjustment must change the stock".to_string(), None));
+        }
+        StockMovementKind::Consumption | StockMovementKind::Cancellation => {
+            return Err(ApiError::Validation(
+                "Consumption and cancellation movements are recorded by order transitions".to_string(),
+                Some(json!({ "kind": movement.kind })),
+            ));
+        }
+        StockMovementKind::Waste => -movement.quantity,
+        StockMovementKind::Purchase | StockMovementKind::Adjustment => movement.quantity,
+    };
+
+    let movement = diesel::insert_into(stock_movement::table)
+        .values((
+            stock_movement::ingredient_id.eq(ingredient_id),
+            stock_movement::kind.eq(movement.kind),
+            stock_movement::quantity.eq(quantity),
+            stock_movement::note.eq(movement.note),
+        ))
+        .get_result::<StockMovement>(conn)?;
+
+    Ok(movement)
+}
+
+#[derive(Debug, FromForm)]
+pub struct StockMovementFilter {
+    pub kind: Option<StockMovementKind>,
+    pub order_id: Option<i32>,
+}
+
+const STOCK_MOVEMENT_SORT_FIELDS: &[&str] = &["id", "created_at", "quantity"];
+
+#[get("/ingredient/<ingredient_id>/movement?<query..>")]
+pub fn get_stock_movements(mut conn: DbConn, ingredient_id: i32, query: ListQuery<StockMovementFilter>) -> Result<Json<Page<StockMovement>>, ApiError> {
+    _get_ingredient(&mut conn, ingredient_id).or_not_found("Ingredient")?;
+
+    let sort = parse_sort(query.page.sort.as_deref(), STOCK_MOVEMENT_SORT_FIELDS)?;
+    let movements = _get_stock_movements(&mut conn, ingredient_id, &query.filter, &query.page, &sort)?;
+
+    Ok(Json(movements))
+}
+
+fn filter_stock_movements(ingredient_id: i32, filter: &StockMovementFilter) -> stock_movement::BoxedQuery<'static, Pg> {
+    let mut query = stock_movement::table
+        .filter(stock_movement::ingredient_id.eq(ingredient_id))
+        .into_boxed();
+
+    if let Some(kind) = filter.kind {
+        query = query.filter(stock_movement::kind.eq(kind));
+    }
+    if let Some(order_id) = filter.order_id {
+        query = query.filter(stock_movement::order_id.eq(order_id));
+    }
+
+    query
+}
+
+/// Newest first unless sorted otherwise.
+fn _get_stock_movements(conn: &mut PgConnection, ingredient_id: i32, filter: &StockMovementFilter, page: &PageParams, sort: &[SortKey]) -> QueryResult<Page<StockMovement>> {
+    let total = filter_stock_movements(ingredient_id, filter)
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let movements = sort_by!(filter_stock_movements(ingredient_id, filter), sort, {
+            "id" => stock_movement::id,
+            "created_at" => stock_movement::created_at,
+            "quantity" => stock_movement::quantity,
+        })
+        .then_order_by(stock_movement::id.desc())
+        .limit(page.limit)
+        .offset(page.offset)
+        .load::<StockMovement>(conn)?;
+
+    Ok(Page::new(movements, total, page))
+}
+
+#[get("/item/<item_id>/recipe")]
+pub fn get_item_recipe(mut conn: DbConn, item_id: i32) -> Result<Json<Vec<RecipeIngredient>>, ApiError> {
+    _get_item(&mut conn, item_id).or_not_found("Item")?;
+    let recipe = _get_item_recipe(&mut conn, item_id)?;
+
+    Ok(Json(recipe))
+}
+
+pub fn _get_item_recipe(conn: &mut PgConnection, item_id: i32) -> QueryResult<Vec<RecipeIngredient>> {
+    recipe_ingredient::table
+        .inner_join(ingredient::table)
+        .filter(recipe_ingredient::item_id.eq(item_id))
+        .order((ingredient::name, ingredient::id))
+        .select((ingredient::id, ingredient::name, ingredient::unit, recipe_ingredient::quantity))
+        .load::<RecipeIngredient>(conn)
+}
+
+#[post("/item/<item_id>/recipe", data = "<ingredient>")]
+pub fn set_item_recipe_ingredient(mut conn: DbConn, item_id: i32, ingredient: Payload<NewRecipeIngredient>) -> Result<Json<Vec<RecipeIngredient>>, ApiError> {
+    _get_item(&mut conn, item_id).or_not_found("Item")?;
+    _get_ingredient(&mut conn, ingredient.ingredient_id).or_not_found("Ingredient")?;
+
+    _set_item_recipe_ingredient(&mut conn, item_id, ingredient.into_inner())?;
+    let recipe = _get_item_recipe(&mut conn, item_id)?;
+
+    Ok(Json(recipe))
+}
+
+fn _set_item_recipe_ingredient(conn: &mut PgConnection, item_id: i32, ingredient: NewRecipeIngredient) -> QueryResult<usize> {
+    diesel::insert_into(recipe_ingredient::table)
+        .values((
+            recipe_ingredient::item_id.eq(item_id),
+            recipe_ingredient::ingredient_id.eq(ingredient.ingredient_id),
+            recipe_ingredient::quantity.eq(ingredient.quantity),
+        ))
+        .on_conflict((recipe_ingredient::item_id, recipe_ingredient::ingredient_id))
+        .do_update()
+        .set(recipe_ingredient::quantity.eq(ingredient.quantity))
+        .execute(conn)
+}
+
+#[delete("/item/<item_id>/recipe/<ingredient_id>")]
+pub fn delete_item_recipe_ingredient(mut conn: DbConn, item_id: i32, ingredient_id: i32) -> Result<Json<Vec<RecipeIngredient>>, ApiError> {
+    let deleted = diesel::delete(recipe_ingredient::table.find((item_id, ingredient_id)))
+        .execute(&mut *conn)?;
+    if deleted == 0 {
+        return Err(ApiError::not_found("Recipe ingredient"));
+    }
+
+    let recipe = _get_item_recipe(&mut conn, item_id)?;
+
+    Ok(Json(recipe))
+}
+
+/// Takes out the ingredients an order's lines use: each line's item recipe
+/// times its quantity, plus for combos the recipes of the components that went
+/// to the kitchen. Modifiers don't touch stock. Called when the order moves to
+/// preparing, later edits to its lines are not deducted.
+pub fn _consume_order_stock(conn: &mut PgConnection, order_id: i32) -> QueryResult<usize> {
+    diesel::sql_query("
+        INSERT INTO stock_movement (ingredient_id, kind, quantity, order_id)
+        SELECT r.ingredient_id, $2, -sum(r.quantity * u.units), $1
+        FROM (
+            SELECT d.item_id, d.quantity AS units
+            FROM order_details d
+            WHERE d.order_id = $1
+            UNION ALL
+            SELECT c.item_id, d.quantity * c.quantity
+            FROM order_line_component c
+            JOIN order_details d ON d.id = c.order_details_id
+            WHERE d.order_id = $1
+        ) u
+        JOIN recipe_ingredient r ON r.item_id = u.item_id
+        GROUP BY r.ingredient_id
+    ")
+        .bind::<Int4, _>(order_id)
+        .bind::<SmallInt, _>(StockMovementKind::Consumption)
+        .execute(conn)
+}
+
+/// Puts back whatever the order still has taken out, nothing if it never
+/// reached preparing.
+pub fn _restore_order_stock(conn: &mut PgConnection, order_id: i32) -> QueryResult<usize> {
+    diesel::sql_query("
+        INSERT INTO stock_movement (ingredient_id, kind, quantity, order_id)
+        SELECT ingredient_id, $3, -sum(quantity), $1
+        FROM stock_movement
+        WHERE order_id = $1 AND kind IN ($2, $3)
+        GROUP BY ingredient_id
+        HAVING sum(quantity) <> 0
+    ")
+        .bind::<Int4, _>(order_id)
+        .bind::<SmallInt, _>(StockMovementKind::Consumption)
+        .bind::<SmallInt, _>(StockMovementKind::Cancellation)
+        .execute(conn)
+}
@@ -1,101 +1,191 @@
-use crate::schema::item;
+use crate::schema::{item, item_price};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use diesel::pg::Pg;
 use diesel::prelude::*;
-use rocket::form::{Form, FromForm};
-use rocket::response::status;
-use crate::DATABASE_URL;
-use serde::Serialize;
-use rocket::serde::json::Json;
+use diesel::result::OptionalEmptyChangesetExtension;
+use rocket::form::FromForm;
+use rocket::time::OffsetDateTime;
+use rocket::State;
+use crate::config::AppConfig;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::libs::availability::{ItemWithAvailability, _get_unavailable_reasons, _with_availability};
+use crate::libs::item_price::to_offset_date_time;
+use crate::money::Money;
+use crate::pagination::{contains_pattern, parse_sort, sort_by, ListQuery, Page, PageParams, SortKey};
+use crate::payload::{double_option, Payload};
+use serde::{Deserialize, Serialize};
+use rocket::serde::json::{json, Json};
 
 
 #[derive(Debug, Queryable, Serialize)]
 pub struct Item {
     pub id: i32,
     pub name: String,
-    pub price: f64,
+    pub price: Money,
     pub description: String,
     pub is_active: bool,
+    pub category_id: Option<i32>,
 }
 
 
-#[derive(Debug, AsChangeset, Insertable, FromForm)]
+#[derive(Debug, AsChangeset, Insertable, FromForm, Deserialize)]
 #[diesel(table_name = item)]
 pub struct NewItem {
     pub name: String,
-    pub price: f64,
+    pub price: Money,
     pub description: String,
     pub is_active: bool,
+    pub category_id: Option<i32>,
 }
 
+#[derive(Debug, AsChangeset, FromForm, Deserialize)]
+#[diesel(table_name = item)]
+pub struct ItemChangeset {
+    pub name: Option<String>,
+    pub price: Option<Money>,
+    pub description: Option<String>,
+    pub is_active: Option<bool>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub category_id: Option<Option<i32>>,
+}
 
-#[get("/item/<item_id>")]
-pub fn get_item(item_id: i32) -> Result<Json<Item>, status::NotFound<String>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
 
-    let item = _get_item(&mut conn, item_id);
+#[get("/item/<item_id>")]
+pub fn get_item(mut conn: DbConn, config: &State<AppConfig>, item_id: i32) -> Result<Json<ItemWithAvailability>, ApiError> {
+    let item = _get_item(&mut conn, item_id).or_not_found("Item")?;
+    let item = _with_availability(&mut conn, vec![item], &config.store.now())?.remove(0);
 
-    match item {
-        Some(item) => Ok(Json(item)),
-        None => Err(status::NotFound("Item not found".to_string())),
-    }
+    Ok(Json(item))
 }
 
-pub fn _get_item(conn: &mut PgConnection, item_id: i32) -> Option<Item> {
+pub fn _get_item(conn: &mut PgConnection, item_id: i32) -> QueryResult<Item> {
     item::table
         .find(item_id)
         .first::<Item>(conn)
-        .optional()
-        .unwrap()
 }
 
-pub fn _get_item_price(conn: &mut PgConnection, item_id: i32) -> QueryResult<f64> {
-    item::table
-        .find(item_id)
-        .select(item::price)
-        .first::<f64>(conn)
+/// The item's price in effect at `at`, from its price history.
+pub fn _get_item_price(conn: &mut PgConnection, item_id: i32, at: OffsetDateTime) -> QueryResult<Money> {
+    let price = item_price::table
+        .filter(item_price::item_id.eq(item_id))
+        .filter(item_price::effective_from.le(at))
+        .order(item_price::effective_from.desc())
+        .select(item_price::price)
+        .first::<Money>(conn)
+        .optional()?;
+
+    match price {
+        Some(price) => Ok(price),
+        None => item::table.find(item_id).select(item::price).first::<Money>(conn),
+    }
 }
 
-#[get("/item")]
-pub fn get_all_items() -> Result<Json<Vec<Item>>, status::NotFound<String>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+/// Query flags of the order line routes for ordering items that can't be
+/// ordered right now: `allow_inactive` for items switched off by hand,
+/// `allow_unavailable` for items out of stock or outside their serving hours.
+#[derive(Debug, Clone, Copy, FromForm)]
+pub struct OrderOverrides {
+    pub allow_inactive: bool,
+    pub allow_unavailable: bool,
+}
 
-    let items = _get_all_items(&mut conn);
+/// Looks up the item for a new order line, priced as of `now`. Inactive and
+/// unavailable items are refused unless the caller explicitly allows them.
+pub fn _get_orderable_item(conn: &mut PgConnection, item_id: i32, overrides: OrderOverrides, now: &DateTime<Tz>) -> Result<Item, ApiError> {
+    let mut item = _get_item(conn, item_id).or_not_found("Item")?;
+    item.price = _get_item_price(conn, item.id, to_offset_date_time(now))?;
+
+    if !item.is_active && !overrides.allow_inactive {
+        return Err(ApiError::Validation(
+            format!("Item {} is inactive, pass allow_inactive=true to order it anyway", item.name),
+            Some(json!({ "item_id": item.id })),
+        ));
+    }
 
-    match items {
-        Some(items) => Ok(Json(items)),
-        None => Err(status::NotFound("Items not found".to_string())),
+    if !overrides.allow_unavailable {
+        if let Some(reason) = _get_unavailable_reasons(conn, std::slice::from_ref(&item), now)?.remove(&item.id) {
+            return Err(ApiError::Validation(
+                format!("Item {} is {}, pass allow_unavailable=true to order it anyway", item.name, reason.describe()),
+                Some(json!({ "item_id": item.id, "reason": reason })),
+            ));
+        }
     }
+
+    Ok(item)
 }
 
-fn _get_all_items(conn: &mut PgConnection) -> Option<Vec<Item>> {
-    item::table
-        .load::<Item>(conn)
-        .optional()
-        .unwrap()
+#[derive(Debug, FromForm)]
+pub struct ItemFilter {
+    pub name: Option<String>,
+    pub is_active: Option<bool>,
+    pub category_id: Option<i32>,
+}
+
+const ITEM_SORT_FIELDS: &[&str] = &["id", "name", "price"];
+
+#[get("/item?<query..>")]
+pub fn get_all_items(mut conn: DbConn, config: &State<AppConfig>, query: ListQuery<ItemFilter>) -> Result<Json<Page<ItemWithAvailability>>, ApiError> {
+    let sort = parse_sort(query.page.sort.as_deref(), ITEM_SORT_FIELDS)?;
+    let items = _get_all_items(&mut conn, &query.filter, &query.page, &sort, &config.store.now())?;
+
+    Ok(Json(items))
+}
+
+fn filter_items(filter: &ItemFilter) -> item::BoxedQuery<'static, Pg> {
+    let mut query = item::table.into_boxed();
+
+    if let Some(name) = &filter.name {
+        query = query.filter(item::name.ilike(contains_pattern(name)));
+    }
+    if let Some(is_active) = filter.is_active {
+        query = query.filter(item::is_active.eq(is_active));
+    }
+    if let Some(category_id) = filter.category_id {
+        query = query.filter(item::category_id.eq(category_id));
+    }
+
+    query
+}
+
+/// A page of items with their availability at `now`.
+fn _get_all_items(conn: &mut PgConnection, filter: &ItemFilter, page: &PageParams, sort: &[SortKey], now: &DateTime<Tz>) -> QueryResult<Page<ItemWithAvailability>> {
+    let total = filter_items(filter)
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let items = sort_by!(filter_items(filter), sort, {
+            "id" => item::id,
+            "name" => item::name,
+            "price" => item::price,
+        })
+        .then_order_by(item::id)
+        .limit(page.limit)
+        .offset(page.offset)
+        .load::<Item>(conn)?;
+
+    Ok(Page::new(_with_availability(conn, items, now)?, total, page))
 }
 
 #[post("/item", data = "<item>")]
-pub fn create_item(item: Form<NewItem>) -> String {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+pub fn create_item(mut conn: DbConn, item: Payload<NewItem>) -> Result<Json<Item>, ApiError> {
+    let item = _create_item(&mut conn, item.into_inner())?;
 
-    let item = diesel::insert_into(item::table)
-        .values(item.into_inner())
-        .get_result::<Item>(&mut conn)
-        .expect("Error creating item");
+    Ok(Json(item))
+}
 
-    format!("{:?}", item)
+fn _create_item(conn: &mut PgConnection, item: NewItem) -> QueryResult<Item> {
+    diesel::insert_into(item::table)
+        .values(item)
+        .get_result::<Item>(conn)
 }
 
 #[put("/item/<item_id>", data = "<item>")]
-pub fn update_item(item_id: i32, item: Form<NewItem>) -> String {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+pub fn update_item(mut conn: DbConn, item_id: i32, item: Payload<NewItem>) -> Result<Json<Item>, ApiError> {
+    let item = _update_item(&mut conn, item_id, item.into_inner()).or_not_found("Item")?;
 
-    let item = _update_item(&mut conn, item_id, item.into_inner());
-
-    format!("{:?}", item)
+    Ok(Json(item))
 }
 
 fn _update_item(conn: &mut PgConnection, item_id: i32, item: NewItem) -> QueryResult<Item> {
@@ -104,26 +194,33 @@ fn _update_item(conn: &mut PgConnection, item_id: i32, item: NewItem) -> QueryRe
         .get_result::<Item>(conn)
 }
 
-#[delete("/item/<item_id>")]
-pub fn delete_item(item_id: i32) -> Result<String, status::NotFound<String>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-
-    let result = _delete_item(&mut conn, item_id);
-
-    match result {
-        Ok(is_deleted) => {
-            if is_deleted == 1 {
-                Ok("Item deleted.".to_string())
-            } else {
-                Err(status::NotFound("Item not found.".to_string()))
-            }
-        }
-        Err(e) => Err(status::NotFound(format!("Error deleting item: {}", e))),
+#[patch("/item/<item_id>", data = "<item>")]
+pub fn patch_item(mut conn: DbConn, item_id: i32, item: Payload<ItemChangeset>) -> Result<Json<Item>, ApiError> {
+    let item = _patch_item(&mut conn, item_id, item.into_inner()).or_not_found("Item")?;
+
+    Ok(Json(item))
+}
+
+fn _patch_item(conn: &mut PgConnection, item_id: i32, item: ItemChangeset) -> QueryResult<Item> {
+    let updated = diesel::update(item::table.find(item_id))
+        .set(item)
+        .get_result::<Item>(conn)
+        .optional_empty_changeset()?;
+
+    match updated {
+        Some(item) => Ok(item),
+        None => _get_item(conn, item_id),
     }
 }
 
-fn _delete_item(conn: &mut PgConnection, item_id: i32) -> QueryResult<usize> {
+#[delete("/item/<item_id>")]
+pub fn delete_item(mut conn: DbConn, item_id: i32) -> Result<Json<Item>, ApiError> {
+    let item = _delete_item(&mut conn, item_id).or_not_found("Item")?;
+
+    Ok(Json(item))
+}
+
+fn _delete_item(conn: &mut PgConnection, item_id: i32) -> QueryResult<Item> {
     diesel::delete(item::table.find(item_id))
-        .execute(conn)
-}
\ No newline at end of file
+        .get_result::<Item>(conn)
+}
@@ -0,0 +1,150 @@
+use crate::schema::item_category;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::result::OptionalEmptyChangesetExtension;
+use rocket::form::FromForm;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::pagination::{contains_pattern, parse_sort, sort_by, ListQuery, Page, PageParams, SortKey};
+use crate::payload::Payload;
+use serde::{Deserialize, Serialize};
+use rocket::serde::json::Json;
+
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct ItemCategory {
+    pub id: i32,
+    pub name: String,
+    pub display_order: i32,
+}
+
+
+#[derive(Debug, AsChangeset, Insertable, FromForm, Deserialize)]
+#[diesel(table_name = item_category)]
+pub struct NewItemCategory {
+    pub name: String,
+    #[field(default = 0)]
+    #[serde(default)]
+    pub display_order: i32,
+}
+
+#[derive(Debug, AsChangeset, FromForm, Deserialize)]
+#[diesel(table_name = item_category)]
+pub struct ItemCategoryChangeset {
+    pub name: Option<String>,
+    pub display_order: Option<i32>,
+}
+
+#[get("/item_category/<item_category_id>")]
+pub fn get_item_category(mut conn: DbConn, item_category_id: i32) -> Result<Json<ItemCategory>, ApiError> {
+    let item_category = _get_item_category(&mut conn, item_category_id).or_not_found("Item category")?;
+
+    Ok(Json(item_category))
+}
+
+pub fn _get_item_category(conn: &mut PgConnection, item_category_id: i32) -> QueryResult<ItemCategory> {
+    item_category::table
+        .find(item_category_id)
+        .first::<ItemCategory>(conn)
+}
+
+#[post("/item_category", data = "<item_category>")]
+pub fn create_item_category(mut conn: DbConn, item_category: Payload<NewItemCategory>) -> Result<Json<ItemCategory>, ApiError> {
+    let item_category = _create_item_category(&mut conn, item_category.into_inner())?;
+
+    Ok(Json(item_category))
+}
+
+fn _create_item_category(conn: &mut PgConnection, item_category: NewItemCategory) -> QueryResult<ItemCategory> {
+    diesel::insert_into(item_category::table)
+        .values(item_category)
+        .get_result::<ItemCategory>(conn)
+}
+
+#[put("/item_category/<item_category_id>", data = "<item_category>")]
+pub fn update_item_category(mut conn: DbConn, item_category_id: i32, item_category: Payload<NewItemCategory>) -> Result<Json<ItemCategory>, ApiError> {
+    let item_category = _update_item_category(&mut conn, item_category_id, item_category.into_inner()).or_not_found("Item category")?;
+
+    Ok(Json(item_category))
+}
+
+fn _update_item_category(conn: &mut PgConnection, item_category_id: i32, item_category: NewItemCategory) -> QueryResult<ItemCategory> {
+    diesel::update(item_category::table.find(item_category_id))
+        .set(item_category)
+        .get_result::<ItemCategory>(conn)
+}
+
+#[patch("/item_category/<item_category_id>", data = "<item_category>")]
+pub fn patch_item_category(mut conn: DbConn, item_category_id: i32, item_category: Payload<ItemCategoryChangeset>) -> Result<Json<ItemCategory>, ApiError> {
+    let item_category = _patch_item_category(&mut conn, item_category_id, item_category.into_inner()).or_not_found("Item category")?;
+
+    Ok(Json(item_category))
+}
+
+fn _patch_item_category(conn: &mut PgConnection, item_category_id: i32, item_category: ItemCategoryChangeset) -> QueryResult<ItemCategory> {
+    let updated = diesel::update(item_category::table.find(item_category_id))
+        .set(item_category)
+        .get_result::<ItemCategory>(conn)
+        .optional_empty_changeset()?;
+
+    match updated {
+        Some(item_category) => Ok(item_category),
+        None => _get_item_category(conn, item_category_id),
+    }
+}
+
+#[delete("/item_category/<item_category_id>")]
+pub fn delete_item_category(mut conn: DbConn, item_category_id: i32) -> Result<Json<ItemCategory>, ApiError> {
+    let item_category = _delete_item_category(&mut conn, item_category_id).or_not_found("Item category")?;
+
+    Ok(Json(item_category))
+}
+
+fn _delete_item_category(conn: &mut PgConnection, item_category_id: i32) -> QueryResult<ItemCategory> {
+    diesel::delete(item_category::table.find(item_category_id))
+        .get_result::<ItemCategory>(conn)
+}
+
+#[derive(Debug, FromForm)]
+pub struct ItemCategoryFilter {
+    pub name: Option<String>,
+}
+
+const ITEM_CATEGORY_SORT_FIELDS: &[&str] = &["id", "name", "display_order"];
+
+#[get("/item_category?<query..>")]
+pub fn get_item_categories(mut conn: DbConn, query: ListQuery<ItemCategoryFilter>) -> Result<Json<Page<ItemCategory>>, ApiError> {
+    let sort = parse_sort(query.page.sort.as_deref(), ITEM_CATEGORY_SORT_FIELDS)?;
+    let item_categories = _get_item_categories(&mut conn, &query.filter, &query.page, &sort)?;
+
+    Ok(Json(item_categories))
+}
+
+fn filter_item_categories(filter: &ItemCategoryFilter) -> item_category::BoxedQuery<'static, Pg> {
+    let mut query = item_category::table.into_boxed();
+
+    if let Some(name) = &filter.name {
+        query = query.filter(item_category::name.ilike(contains_pattern(name)));
+    }
+
+    query
+}
+
+fn _get_item_categories(conn: &mut PgConnection, filter: &ItemCategoryFilter, page: &PageParams, sort: &[SortKey]) -> QueryResult<Page<ItemCategory>> {
+    let total = filter_item_categories(filter)
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let item_categories = sort_by!(filter_item_categories(filter), sort, {
+            "id" => item_category::id,
+            "name" => item_category::name,
+            "display_order" => item_category::display_order,
+        })
+        .then_order_by((item_category::display_order, item_category::id))
+        .limit(page.limit)
+        .offset(page.offset)
+        .load::<ItemCategory>(conn)?;
+
+    Ok(Page::new(item_categories, total, page))
+}
+
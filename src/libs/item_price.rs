@@ -0,0 +1,277 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::schema::item_price;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use rocket::form::{self, FromForm, FromFormField, ValueField};
+use rocket::serde::json::{json, Json};
+use rocket::time::{Duration, OffsetDateTime};
+use rocket::State;
+use crate::config::AppConfig;
+use crate::db::{DbConn, DbPool};
+use crate::error::{ApiError, OrNotFound};
+use crate::libs::item::_get_item;
+use crate::money::Money;
+use crate::payload::Payload;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+
+/// A price an item had, has or is scheduled to have from `effective_from` on.
+#[derive(Debug, Queryable, Serialize)]
+pub struct ItemPrice {
+    pub id: i32,
+    pub item_id: i32,
+    pub price: Money,
+    pub effective_from: OffsetDateTime,
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, FromForm, Deserialize)]
+pub struct NewScheduledPrice {
+    pub price: Money,
+    pub effective_from: EffectiveFrom,
+}
+
+/// When a scheduled price starts: an RFC 3339 timestamp, or a wall-clock time
+/// in the store timezone such as `"2026-11-01 00:00"`.
+#[derive(Debug, Clone, Copy)]
+pub enum EffectiveFrom {
+    Instant(DateTime<FixedOffset>),
+    Local(NaiveDateTime),
+}
+
+const LOCAL_FORMATS: &[&str] = &["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"];
+
+impl EffectiveFrom {
+    pub fn parse(value: &str) -> Result<EffectiveFrom, String> {
+        let value = value.trim();
+
+        if let Ok(instant) = DateTime::parse_from_rfc3339(value) {
+            return Ok(EffectiveFrom::Instant(instant));
+        }
+
+        LOCAL_FORMATS.iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            .map(EffectiveFrom::Local)
+            .ok_or_else(|| format!("`{}` is not a date and time such as \"2026-11-01 00:00\"", value))
+    }
+
+    /// The instant this is in `timezone`. A local time skipped by a DST change
+    /// has none, one repeated by it is taken at its first occurrence.
+    pub fn resolve(self, timezone: Tz) -> Option<OffsetDateTime> {
+        let timestamp = match self {
+            EffectiveFrom::Instant(instant) => instant.timestamp(),
+            EffectiveFrom::Local(local) => timezone.from_local_datetime(&local).earliest()?.timestamp(),
+        };
+
+        Some(OffsetDateTime::UNIX_EPOCH + Duration::seconds(timestamp))
+    }
+}
+
+impl fmt::Display for EffectiveFrom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EffectiveFrom::Instant(instant) => write!(f, "{}", instant.to_rfc3339()),
+            EffectiveFrom::Local(local) => write!(f, "{}", local.format("%Y-%m-%d %H:%M:%S")),
+        }
+    }
+}
+
+struct EffectiveFromVisitor;
+
+impl Visitor<'_> for EffectiveFromVisitor {
+    type Value = EffectiveFrom;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a date and time such as \"2026-11-01 00:00\"")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<EffectiveFrom, E> {
+        EffectiveFrom::parse(value).map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for EffectiveFrom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<EffectiveFrom, D::Error> {
+        deserializer.deserialize_str(EffectiveFromVisitor)
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for EffectiveFrom {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        Ok(EffectiveFrom::parse(field.value).map_err(form::Error::validation)?)
+    }
+}
+
+/// `now` as stored in timestamp columns.
+pub fn to_offset_date_time<T: TimeZone>(now: &DateTime<T>) -> OffsetDateTime {
+    OffsetDateTime::UNIX_EPOCH + Duration::milliseconds(now.timestamp_millis())
+}
+
+#[get("/item/<item_id>/prices")]
+pub fn get_item_prices(mut conn: DbConn, item_id: i32) -> Result<Json<Vec<ItemPrice>>, ApiError> {
+    _get_item(&mut conn, item_id).or_not_found("Item")?;
+    let prices = _get_item_prices(&mut conn, item_id)?;
+
+    Ok(Json(prices))
+}
+
+/// The item's price history, scheduled prices included, latest first.
+pub fn _get_item_prices(conn: &mut PgConnection, item_id: i32) -> QueryResult<Vec<ItemPrice>> {
+    item_price::table
+        .filter(item_price::item_id.eq(item_id))
+        .order(item_price::effective_from.desc())
+        .load::<ItemPrice>(conn)
+}
+
+#[post("/item/<item_id>/prices", data = "<price>")]
+pub fn schedule_item_price(mut conn: DbConn, config: &State<AppConfig>, item_id: i32, price: Payload<NewScheduledPrice>) -> Result<Json<ItemPrice>, ApiError> {
+    _get_item(&mut conn, item_id).or_not_found("Item")?;
+    let price = _schedule_item_price(&mut conn, item_id, price.into_inner(), &config.store.timezone)?;
+
+    Ok(Json(price))
+}
+
+/// Only future prices can be scheduled, the current one is changed through
+/// `PUT`/`PATCH /item/<id>`.
+pub fn _schedule_item_price(conn: &mut PgConnection, item_id: i32, price: NewScheduledPrice, timezone: &Tz) -> Result<ItemPrice, ApiError> {
+    if price.price < Money::ZERO {
+        return Err(ApiError::Validation(
+            "price can't be negative".to_string(),
+            Some(json!({ "price": price.price })),
+        ));
+    }
+
+    let effective_from = price.effective_from.resolve(*timezone)
+        .ok_or_else(|| ApiError::Validation(
+            format!("{} does not exist in {}", price.effective_from, timezone),
+            Some(json!({ "effective_from": price.effective_from.to_string() })),
+        ))?;
+
+    if effective_from <= OffsetDateTime::now_utc() {
+        return Err(ApiError::Validation(
+            "effective_from must be in the future, change the item's price to change it now".to_string(),
+            Some(json!({ "effective_from": effective_from })),
+        ));
+    }
+
+    let price = diesel::insert_into(item_price::table)
+        .values((
+            item_price::item_id.eq(item_id),
+            item_price::price.eq(price.price),
+            item_price::effective_from.eq(effective_from),
+        ))
+        .get_result::<ItemPrice>(conn)?;
+
+    Ok(price)
+}
+
+#[delete("/item/<item_id>/prices/<price_id>")]
+pub fn delete_scheduled_item_price(mut conn: DbConn, item_id: i32, price_id: i32) -> Result<Json<ItemPrice>, ApiError> {
+    let price = conn.transaction(|conn| _delete_scheduled_item_price(conn, item_id, price_id))?;
+
+    Ok(Json(price))
+}
+
+/// Cancels a price that hasn't taken effect yet. Past prices are history and stay.
+pub fn _delete_scheduled_item_price(conn: &mut PgConnection, item_id: i32, price_id: i32) -> Result<ItemPrice, ApiError> {
+    let price = item_price::table
+        .find(price_id)
+        .filter(item_price::item_id.eq(item_id))
+        .for_update()
+        .first::<ItemPrice>(conn)
+        .or_not_found("Item price")?;
+
+    if price.effective_from <= OffsetDateTime::now_utc() {
+        return Err(ApiError::Conflict(
+            "Only prices that haven't taken effect yet can be deleted".to_string(),
+            Some(json!({ "effective_from": price.effective_from })),
+        ));
+    }
+
+    diesel::delete(item_price::table.find(price_id))
+        .execute(conn)?;
+
+    Ok(price)
+}
+
+/// Copies every scheduled price that has taken effect into `item.price`,
+/// returning how many items changed. Run periodically, see `mount::rocket`.
+pub fn _apply_due_prices(conn: &mut PgConnection) -> QueryResult<usize> {
+    diesel::sql_query("
+        UPDATE item i
+        SET price = p.price
+        FROM (
+            SELECT DISTINCT ON (item_id) item_id, price
+            FROM item_price
+            WHERE effective_from <= now()
+            ORDER BY item_id, effective_from DESC
+        ) p
+        WHERE p.item_id = i.id AND i.price <> p.price
+    ")
+        .execute(conn)
+}
+
+/// `_apply_due_prices` on a connection of its own, for the background job.
+pub fn apply_due_prices(pool: &DbPool) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+
+    Ok(_apply_due_prices(&mut conn)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(value: &str, timezone: Tz) -> Option<OffsetDateTime> {
+        EffectiveFrom::parse(value).unwrap().resolve(timezone)
+    }
+
+    #[test]
+    fn parses_rfc3339_as_an_instant() {
+        let parsed = EffectiveFrom::parse("2026-11-01T03:00:00Z").unwrap();
+
+        assert!(matches!(parsed, EffectiveFrom::Instant(_)));
+        assert_eq!(resolve("2026-11-01T03:00:00Z", chrono_tz::Asia::Tokyo).unwrap().unix_timestamp(), 1_793_502_000);
+    }
+
+    #[test]
+    fn parses_every_local_format() {
+        for value in ["2026-11-01 00:00", "2026-11-01 00:00:00", "2026-11-01T00:00", " 2026-11-01T00:00:00 "] {
+            let parsed = EffectiveFrom::parse(value).unwrap();
+
+            assert!(matches!(parsed, EffectiveFrom::Local(_)), "{}", value);
+            assert_eq!(parsed.to_string(), "2026-11-01 00:00:00");
+        }
+    }
+
+    #[test]
+    fn rejects_anything_else() {
+        for value in ["", "tomorrow", "2026-11-01", "01/11/2026 00:00", "2026-13-01 00:00"] {
+            assert!(EffectiveFrom::parse(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn resolves_local_times_in_the_store_timezone() {
+        let resolved = resolve("2026-11-01 00:00", chrono_tz::America::Sao_Paulo).unwrap();
+
+        assert_eq!(resolved.unix_timestamp(), 1_793_502_000);
+    }
+
+    #[test]
+    fn local_times_skipped_by_dst_do_not_resolve() {
+        assert_eq!(resolve("2026-03-08 02:30", chrono_tz::America::New_York), None);
+    }
+
+    #[test]
+    fn local_times_repeated_by_dst_take_the_first_occurrence() {
+        let resolved = resolve("2026-11-01 01:30", chrono_tz::America::New_York).unwrap();
+
+        assert_eq!(resolved.unix_timestamp(), resolve("2026-11-01T01:30:00-04:00", Tz::UTC).unwrap().unix_timestamp());
+    }
+}
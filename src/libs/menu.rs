@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use crate::schema::{item, item_category};
+use diesel::prelude::*;
+use rocket::serde::json::Json;
+use crate::db::DbConn;
+use crate::error::ApiError;
+use crate::libs::item::Item;
+use crate::libs::item_category::ItemCategory;
+use crate::libs::modifier::{ItemModifierGroup, _get_modifier_groups_of_items};
+use serde::Serialize;
+
+
+#[derive(Debug, Serialize)]
+pub struct MenuItem {
+    #[serde(flatten)]
+    pub item: Item,
+    pub modifier_groups: Vec<ItemModifierGroup>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MenuCategory {
+    pub id: i32,
+    pub name: String,
+    pub display_order: i32,
+    pub items: Vec<MenuItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Menu {
+    pub categories: Vec<MenuCategory>,
+    pub uncategorized: Vec<MenuItem>,
+}
+
+#[get("/menu")]
+pub fn get_menu(mut conn: DbConn) -> Result<Json<Menu>, ApiError> {
+    let menu = _get_menu(&mut conn)?;
+
+    Ok(Json(menu))
+}
+
+/// Active items grouped by category in display order, items sorted by name
+/// and carrying their modifier groups. Categories without active items are
+/// left out.
+pub fn _get_menu(conn: &mut PgConnection) -> QueryResult<Menu> {
+    let categories = item_category::table
+        .order((item_category::display_order, item_category::name, item_category::id))
+        .load::<ItemCategory>(conn)?;
+
+    let items = item::table
+        .filter(item::is_active.eq(true))
+        .order((item::name, item::id))
+        .load::<Item>(conn)?;
+
+    let item_ids = items.iter().map(|item| item.id).collect::<Vec<_>>();
+    let mut modifier_groups = HashMap::<i32, Vec<ItemModifierGroup>>::new();
+    for (item_id, group) in _get_modifier_groups_of_items(conn, &item_ids)? {
+        modifier_groups.entry(item_id).or_default().push(group);
+    }
+
+    let (categorized, uncategorized): (Vec<MenuItem>, Vec<MenuItem>) = items
+        .into_iter()
+        .map(|item| MenuItem {
+            modifier_groups: modifier_groups.remove(&item.id).unwrap_or_default(),
+            item,
+        })
+        .partition(|menu_item| menu_item.item.category_id.is_some());
+
+    let mut categories = categories
+        .into_iter()
+        .map(|category| MenuCategory {
+            id: category.id,
+            name: category.name,
+            display_order: category.display_order,
+            items: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+
+    for menu_item in categorized {
+        if let Some(category) = categories.iter_mut().find(|category| Some(category.id) == menu_item.item.category_id) {
+            category.items.push(menu_item);
+        }
+    }
+
+    categories.retain(|category| !category.items.is_empty());
+
+    Ok(Menu { categories, uncategorized })
+}
@@ -0,0 +1,446 @@
+use crate::schema::{item_modifier_group, modifier_group, modifier_option};
+use diesel::prelude::*;
+use diesel::result::OptionalEmptyChangesetExtension;
+use rocket::form::FromForm;
+use rocket::serde::json::{json, Json, Value};
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::libs::item::_get_item;
+use crate::money::Money;
+use crate::payload::Payload;
+use serde::{Deserialize, Serialize};
+
+
+/// A choice offered on items, e.g. "Size" (exactly one) or "Extras" (up to 5).
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct ModifierGroup {
+    pub id: i32,
+    pub name: String,
+    pub min_selections: i32,
+    pub max_selections: i32,
+}
+
+#[derive(Debug, AsChangeset, Insertable, FromForm, Deserialize)]
+#[diesel(table_name = modifier_group)]
+pub struct NewModifierGroup {
+    pub name: String,
+    #[field(default = 0)]
+    #[serde(default)]
+    pub min_selections: i32,
+    #[field(default = 1)]
+    #[serde(default = "one")]
+    pub max_selections: i32,
+}
+
+fn one() -> i32 {
+    1
+}
+
+#[derive(Debug, AsChangeset, FromForm, Deserialize)]
+#[diesel(table_name = modifier_group)]
+pub struct ModifierGroupChangeset {
+    pub name: Option<String>,
+    pub min_selections: Option<i32>,
+    pub max_selections: Option<i32>,
+}
+
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct ModifierOption {
+    pub id: i32,
+    pub group_id: i32,
+    pub name: String,
+    pub price_delta: Money,
+    pub is_active: bool,
+}
+
+#[derive(Debug, AsChangeset, Insertable, FromForm, Deserialize)]
+#[diesel(table_name = modifier_option)]
+pub struct NewModifierOption {
+    pub name: String,
+    #[field(default = Money::ZERO)]
+    #[serde(default)]
+    pub price_delta: Money,
+    #[field(default = true)]
+    #[serde(default = "active")]
+    pub is_active: bool,
+}
+
+fn active() -> bool {
+    true
+}
+
+#[derive(Debug, AsChangeset, FromForm, Deserialize)]
+#[diesel(table_name = modifier_option)]
+pub struct ModifierOptionChangeset {
+    pub name: Option<String>,
+    pub price_delta: Option<Money>,
+    pub is_active: Option<bool>,
+}
+
+/// A group as attached to one item, with its active options.
+#[derive(Debug, Serialize)]
+pub struct ItemModifierGroup {
+    #[serde(flatten)]
+    pub group: ModifierGroup,
+    pub display_order: i32,
+    pub options: Vec<ModifierOption>,
+}
+
+#[derive(Debug, FromForm, Deserialize)]
+pub struct ItemModifierGroupLink {
+    pub group_id: i32,
+    #[field(default = 0)]
+    #[serde(default)]
+    pub display_order: i32,
+}
+
+#[get("/modifier_group/<group_id>")]
+pub fn get_modifier_group(mut conn: DbConn, group_id: i32) -> Result<Json<ModifierGroup>, ApiError> {
+    let group = _get_modifier_group(&mut conn, group_id).or_not_found("Modifier group")?;
+
+    Ok(Json(group))
+}
+
+pub fn _get_modifier_group(conn: &mut PgConnection, group_id: i32) -> QueryResult<ModifierGroup> {
+    modifier_group::table
+        .find(group_id)
+        .first::<ModifierGroup>(conn)
+}
+
+#[get("/modifier_group")]
+pub fn get_modifier_groups(mut conn: DbConn) -> Result<Json<Vec<ModifierGroup>>, ApiError> {
+    let groups = _get_modifier_groups(&mut conn)?;
+
+    Ok(Json(groups))
+}
+
+pub fn _get_modifier_groups(conn: &mut PgConnection) -> QueryResult<Vec<ModifierGroup>> {
+    modifier_group::table
+        .order(modifier_group::id)
+        .load::<ModifierGroup>(conn)
+}
+
+#[post("/modifier_group", data = "<group>")]
+pub fn create_modifier_group(mut conn: DbConn, group: Payload<NewModifierGroup>) -> Result<Json<ModifierGroup>, ApiError> {
+    let group = _create_modifier_group(&mut conn, group.into_inner())?;
+
+    Ok(Json(group))
+}
+
+fn _create_modifier_group(conn: &mut PgConnection, group: NewModifierGroup) -> QueryResult<ModifierGroup> {
+    diesel::insert_into(modifier_group::table)
+        .values(group)
+        .get_result::<ModifierGroup>(conn)
+}
+
+#[put("/modifier_group/<group_id>", data = "<group>")]
+pub fn update_modifier_group(mut conn: DbConn, group_id: i32, group: Payload<NewModifierGroup>) -> Result<Json<ModifierGroup>, ApiError> {
+    let group = _update_modifier_group(&mut conn, group_id, group.into_inner()).or_not_found("Modifier group")?;
+
+    Ok(Json(group))
+}
+
+fn _update_modifier_group(conn: &mut PgConnection, group_id: i32, group: NewModifierGroup) -> QueryResult<ModifierGroup> {
+    diesel::update(modifier_group::table.find(group_id))
+        .set(group)
+        .get_result::<ModifierGroup>(conn)
+}
+
+#[patch("/modifier_group/<group_id>", data = "<group>")]
+pub fn patch_modifier_group(mut conn: DbConn, group_id: i32, group: Payload<ModifierGroupChangeset>) -> Result<Json<ModifierGroup>, ApiError> {
+    let group = _patch_modifier_group(&mut conn, group_id, group.into_inner()).or_not_found("Modifier group")?;
+
+    Ok(Json(group))
+}
+
+fn _patch_modifier_group(conn: &mut PgConnection, group_id: i32, group: ModifierGroupChangeset) -> QueryResult<ModifierGroup> {
+    let updated = diesel::update(modifier_group::table.find(group_id))
+        .set(group)
+        .get_result::<ModifierGroup>(conn)
+        .optional_empty_changeset()?;
+
+    match updated {
+        Some(group) => Ok(group),
+        None => _get_modifier_group(conn, group_id),
+    }
+}
+
+#[delete("/modifier_group/<group_id>")]
+pub fn delete_modifier_group(mut conn: DbConn, group_id: i32) -> Result<Json<ModifierGroup>, ApiError> {
+    let group = _delete_modifier_group(&mut conn, group_id).or_not_found("Modifier group")?;
+
+    Ok(Json(group))
+}
+
+fn _delete_modifier_group(conn: &mut PgConnection, group_id: i32) -> QueryResult<ModifierGroup> {
+    diesel::delete(modifier_group::table.find(group_id))
+        .get_result::<ModifierGroup>(conn)
+}
+
+#[get("/modifier_group/<group_id>/option")]
+pub fn get_modifier_options(mut conn: DbConn, group_id: i32) -> Result<Json<Vec<ModifierOption>>, ApiError> {
+    _get_modifier_group(&mut conn, group_id).or_not_found("Modifier group")?;
+    let options = _get_modifier_options(&mut conn, group_id)?;
+
+    Ok(Json(options))
+}
+
+pub fn _get_modifier_options(conn: &mut PgConnection, group_id: i32) -> QueryResult<Vec<ModifierOption>> {
+    modifier_option::table
+        .filter(modifier_option::group_id.eq(group_id))
+        .order(modifier_option::id)
+        .load::<ModifierOption>(conn)
+}
+
+#[post("/modifier_group/<group_id>/option", data = "<option>")]
+pub fn create_modifier_option(mut conn: DbConn, group_id: i32, option: Payload<NewModifierOption>) -> Result<Json<ModifierOption>, ApiError> {
+    _get_modifier_group(&mut conn, group_id).or_not_found("Modifier group")?;
+    let option = _create_modifier_option(&mut conn, group_id, option.into_inner())?;
+
+    Ok(Json(option))
+}
+
+fn _create_modifier_option(conn: &mut PgConnection, group_id: i32, option: NewModifierOption) -> QueryResult<ModifierOption> {
+    diesel::insert_into(modifier_option::table)
+        .values((option, modifier_option::group_id.eq(group_id)))
+        .get_result::<ModifierOption>(conn)
+}
+
+#[get("/modifier_option/<option_id>")]
+pub fn get_modifier_option(mut conn: DbConn, option_id: i32) -> Result<Json<ModifierOption>, ApiError> {
+    let option = _get_modifier_option(&mut conn, option_id).or_not_found("Modifier option")?;
+
+    Ok(Json(option))
+}
+
+pub fn _get_modifier_option(conn: &mut PgConnection, option_id: i32) -> QueryResult<ModifierOption> {
+    modifier_option::table
+        .find(option_id)
+        .first::<ModifierOption>(conn)
+}
+
+#[put("/modifier_option/<option_id>", data = "<option>")]
+pub fn update_modifier_option(mut conn: DbConn, option_id: i32, option: Payload<NewModifierOption>) -> Result<Json<ModifierOption>, ApiError> {
+    let option = _update_modifier_option(&mut conn, option_id, option.into_inner()).or_not_found("Modifier option")?;
+
+    Ok(Json(option))
+}
+
+fn _update_modifier_option(conn: &mut PgConnection, option_id: i32, option: NewModifierOption) -> QueryResult<ModifierOption> {
+    diesel::update(modifier_option::table.find(option_id))
+        .set(option)
+        .get_result::<ModifierOption>(conn)
+}
+
+#[patch("/modifier_option/<option_id>", data = "<option>")]
+pub fn patch_modifier_option(mut conn: DbConn, option_id: i32, option: Payload<ModifierOptionChangeset>) -> Result<Json<ModifierOption>, ApiError> {
+    let option = _patch_modifier_option(&mut conn, option_id, option.into_inner()).or_not_found("Modifier option")?;
+
+    Ok(Json(option))
+}
+
+fn _patch_modifier_option(conn: &mut PgConnection, option_id: i32, option: ModifierOptionChangeset) -> QueryResult<ModifierOption> {
+    let updated = diesel::update(modifier_option::table.find(option_id))
+        .set(option)
+        .get_result::<ModifierOption>(conn)
+        .optional_empty_changeset()?;
+
+    match updated {
+        Some(option) => Ok(option),
+        None => _get_modifier_option(conn, option_id),
+    }
+}
+
+#[delete("/modifier_option/<option_id>")]
+pub fn delete_modifier_option(mut conn: DbConn, option_id: i32) -> Result<Json<ModifierOption>, ApiError> {
+    let option = _delete_modifier_option(&mut conn, option_id).or_not_found("Modifier option")?;
+
+    Ok(Json(option))
+}
+
+fn _delete_modifier_option(conn: &mut PgConnection, option_id: i32) -> QueryResult<ModifierOption> {
+    diesel::delete(modifier_option::table.find(option_id))
+        .get_result::<ModifierOption>(conn)
+}
+
+#[get("/item/<item_id>/modifier_group")]
+pub fn get_item_modifier_groups(mut conn: DbConn, item_id: i32) -> Result<Json<Vec<ItemModifierGroup>>, ApiError> {
+    _get_item(&mut conn, item_id).or_not_found("Item")?;
+    let groups = _get_item_modifier_groups(&mut conn, item_id)?;
+
+    Ok(Json(groups))
+}
+
+/// The groups attached to an item in display order, each with its active options.
+pub fn _get_item_modifier_groups(conn: &mut PgConnection, item_id: i32) -> QueryResult<Vec<ItemModifierGroup>> {
+    let groups = _get_modifier_groups_of_items(conn, &[item_id])?
+        .into_iter()
+        .map(|(_, group)| group)
+        .collect();
+
+    Ok(groups)
+}
+
+/// Same as `_get_item_modifier_groups` for many items at once, as
+/// `(item_id, group)` pairs ordered by item.
+pub fn _get_modifier_groups_of_items(conn: &mut PgConnection, item_ids: &[i32]) -> QueryResult<Vec<(i32, ItemModifierGroup)>> {
+    let groups = item_modifier_group::table
+        .inner_join(modifier_group::table)
+        .filter(item_modifier_group::item_id.eq_any(item_ids))
+        .order((item_modifier_group::item_id, item_modifier_group::display_order, modifier_group::id))
+        .select((item_modifier_group::item_id, modifier_group::all_columns, item_modifier_group::display_order))
+        .load::<(i32, ModifierGroup, i32)>(conn)?;
+
+    let group_ids = groups.iter().map(|(_, group, _)| group.id).collect::<Vec<_>>();
+    let options = modifier_option::table
+        .filter(modifier_option::group_id.eq_any(&group_ids))
+        .filter(modifier_option::is_active.eq(true))
+        .order(modifier_option::id)
+        .load::<ModifierOption>(conn)?;
+
+    Ok(groups
+        .into_iter()
+        .map(|(item_id, group, display_order)| (item_id, ItemModifierGroup {
+            options: options.iter().filter(|option| option.group_id == group.id).cloned().collect(),
+            group,
+            display_order,
+        }))
+        .collect())
+}
+
+#[post("/item/<item_id>/modifier_group", data = "<link>")]
+pub fn attach_item_modifier_group(mut conn: DbConn, item_id: i32, link: Payload<ItemModifierGroupLink>) -> Result<Json<Vec<ItemModifierGroup>>, ApiError> {
+    _get_item(&mut conn, item_id).or_not_found("Item")?;
+    _get_modifier_group(&mut conn, link.group_id).or_not_found("Modifier group")?;
+
+    _attach_item_modifier_group(&mut conn, item_id, link.into_inner())?;
+    let groups = _get_item_modifier_groups(&mut conn, item_id)?;
+
+    Ok(Json(groups))
+}
+
+fn _attach_item_modifier_group(conn: &mut PgConnection, item_id: i32, link: ItemModifierGroupLink) -> QueryResult<usize> {
+    diesel::insert_into(item_modifier_group::table)
+        .values((
+            item_modifier_group::item_id.eq(item_id),
+            item_modifier_group::group_id.eq(link.group_id),
+            item_modifier_group::display_order.eq(link.display_order),
+        ))
+        .on_conflict((item_modifier_group::item_id, item_modifier_group::group_id))
+        .do_update()
+        .set(item_modifier_group::display_order.eq(link.display_order))
+        .execute(conn)
+}
+
+#[delete("/item/<item_id>/modifier_group/<group_id>")]
+pub fn detach_item_modifier_group(mut conn: DbConn, item_id: i32, group_id: i32) -> Result<Json<Vec<ItemModifierGroup>>, ApiError> {
+    let detached = _detach_item_modifier_group(&mut conn, item_id, group_id)?;
+    if detached == 0 {
+        return Err(ApiError::not_found("Item modifier group"));
+    }
+
+    let groups = _get_item_modifier_groups(&mut conn, item_id)?;
+
+    Ok(Json(groups))
+}
+
+fn _detach_item_modifier_group(conn: &mut PgConnection, item_id: i32, group_id: i32) -> QueryResult<usize> {
+    diesel::delete(item_modifier_group::table.find((item_id, group_id)))
+        .execute(conn)
+}
+
+/// An option picked for an order line, with the names and price to snapshot.
+#[derive(Debug)]
+pub struct SelectedOption {
+    pub option_id: i32,
+    pub group_name: String,
+    pub option_name: String,
+    pub price_delta: Money,
+}
+
+#[derive(Debug)]
+pub struct Selection {
+    pub options: Vec<SelectedOption>,
+    pub price_delta: Money,
+    /// Sorted option ids, what tells two lines of the same item apart.
+    pub key: String,
+}
+
+fn selection_error(message: String, details: Value) -> ApiError {
+    ApiError::Validation(message, Some(details))
+}
+
+/// Checks the options picked for an item against the item's modifier groups:
+/// every option must be active and belong to one of them, and every group's
+/// min/max selections must hold.
+pub fn _resolve_selection(conn: &mut PgConnection, item_id: i32, option_ids: &[i32]) -> Result<Selection, ApiError> {
+    let mut option_ids = option_ids.to_vec();
+    option_ids.sort_unstable();
+    if let Some(pair) = option_ids.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(selection_error(
+            format!("Modifier option {} is selected more than once", pair[0]),
+            json!({ "option_id": pair[0] }),
+        ));
+    }
+
+    let groups = _get_item_modifier_groups(conn, item_id)?;
+    let options = modifier_option::table
+        .filter(modifier_option::id.eq_any(&option_ids))
+        .load::<ModifierOption>(conn)?;
+
+    let mut selected = Vec::with_capacity(option_ids.len());
+    for option_id in &option_ids {
+        let option = options.iter()
+            .find(|option| option.id == *option_id)
+            .ok_or_else(|| selection_error(
+                format!("Modifier option {} does not exist", option_id),
+                json!({ "option_id": option_id }),
+            ))?;
+
+        if !option.is_active {
+            return Err(selection_error(
+                format!("Modifier option {} is not available", option.name),
+                json!({ "option_id": option_id }),
+            ));
+        }
+
+        let group = groups.iter()
+            .find(|group| group.group.id == option.group_id)
+            .ok_or_else(|| selection_error(
+                format!("Modifier option {} cannot be chosen for this item", option.name),
+                json!({ "option_id": option_id }),
+            ))?;
+
+        selected.push(SelectedOption {
+            option_id: option.id,
+            group_name: group.group.name.clone(),
+            option_name: option.name.clone(),
+            price_delta: option.price_delta,
+        });
+    }
+
+    for ItemModifierGroup { group, .. } in &groups {
+        let count = options.iter().filter(|option| option.group_id == group.id).count() as i32;
+
+        if count < group.min_selections || count > group.max_selections {
+            let message = if count < group.min_selections {
+                format!("Choose at least {} option(s) for {}", group.min_selections, group.name)
+            } else {
+                format!("Choose at most {} option(s) for {}", group.max_selections, group.name)
+            };
+
+            return Err(selection_error(message, json!({
+                "group_id": group.id,
+                "min_selections": group.min_selections,
+                "max_selections": group.max_selections,
+                "selected": count,
+            })));
+        }
+    }
+
+    Ok(Selection {
+        price_delta: selected.iter().map(|option| option.price_delta).sum(),
+        key: option_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(","),
+        options: selected,
+    })
+}
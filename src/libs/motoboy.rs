@@ -1,10 +1,15 @@
 use crate::schema::motoboy;
+use diesel::pg::Pg;
 use diesel::prelude::*;
-use rocket::form::{Form, FromForm};
-use crate::DATABASE_URL;
-use serde::Serialize;
+use diesel::result::OptionalEmptyChangesetExtension;
+use rocket::form::FromForm;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::money::Money;
+use crate::pagination::{contains_pattern, parse_sort, sort_by, ListQuery, Page, PageParams, SortKey};
+use crate::payload::Payload;
+use serde::{Deserialize, Serialize};
 use rocket::serde::json::Json;
-use rocket::response::status;
 
 
 #[derive(Debug, Queryable, Serialize)]
@@ -12,52 +17,47 @@ pub struct Motoboy {
     pub id: i32,
     pub name: String,
     pub phone: String,
-    pub daily_salary: f64,
+    pub daily_salary: Money,
     pub is_active: bool,
 }
 
 
-#[derive(Debug, AsChangeset, Insertable, FromForm)]
+#[derive(Debug, AsChangeset, Insertable, FromForm, Deserialize)]
 #[diesel(table_name = motoboy)]
 pub struct NewMotoboy {
     pub name: String,
     pub phone: String,
-    pub daily_salary: f64,
+    pub daily_salary: Money,
     pub is_active: bool,
 }
 
-#[get("/motoboy/<motoboy_id>")]
-pub fn get_motoboy(motoboy_id: i32) -> Result<Json<Motoboy>, status::NotFound<String>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+#[derive(Debug, AsChangeset, FromForm, Deserialize)]
+#[diesel(table_name = motoboy)]
+pub struct MotoboyChangeset {
+    pub name: Option<String>,
+    pub phone: Option<String>,
+    pub daily_salary: Option<Money>,
+    pub is_active: Option<bool>,
+}
 
-    let motoboy = _get_motoboy(&mut conn, motoboy_id);
+#[get("/motoboy/<motoboy_id>")]
+pub fn get_motoboy(mut conn: DbConn, motoboy_id: i32) -> Result<Json<Motoboy>, ApiError> {
+    let motoboy = _get_motoboy(&mut conn, motoboy_id).or_not_found("Motoboy")?;
 
-    match motoboy {
-        Some(motoboy) => Ok(Json(motoboy)),
-        None => Err(status::NotFound("Motoboy not found".to_string())),
-    }
+    Ok(Json(motoboy))
 }
 
-pub fn _get_motoboy(conn: &mut PgConnection, motoboy_id: i32) -> Option<Motoboy> {
+pub fn _get_motoboy(conn: &mut PgConnection, motoboy_id: i32) -> QueryResult<Motoboy> {
     motoboy::table
         .find(motoboy_id)
         .first::<Motoboy>(conn)
-        .optional()
-        .unwrap()
 }
 
 #[post("/motoboy", data = "<motoboy>")]
-pub fn create_motoboy(motoboy: Form<NewMotoboy>) -> Result<Json<Motoboy>, status::BadRequest<String>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-
-    let motoboy = _create_motoboy(&mut conn, motoboy.into_inner());
+pub fn create_motoboy(mut conn: DbConn, motoboy: Payload<NewMotoboy>) -> Result<Json<Motoboy>, ApiError> {
+    let motoboy = _create_motoboy(&mut conn, motoboy.into_inner())?;
 
-    match motoboy {
-        Ok(motoboy) => Ok(Json(motoboy)),
-        Err(_) => Err(status::BadRequest(Some("Error creating motoboy".to_string()))),
-    }
+    Ok(Json(motoboy))
 }
 
 fn _create_motoboy(conn: &mut PgConnection, motoboy: NewMotoboy) -> QueryResult<Motoboy> {
@@ -67,16 +67,10 @@ fn _create_motoboy(conn: &mut PgConnection, motoboy: NewMotoboy) -> QueryResult<
 }
 
 #[put("/motoboy/<motoboy_id>", data = "<motoboy>")]
-pub fn update_motoboy(motoboy_id: i32, motoboy: Form<NewMotoboy>) -> Result<Json<Motoboy>, status::BadRequest<String>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+pub fn update_motoboy(mut conn: DbConn, motoboy_id: i32, motoboy: Payload<NewMotoboy>) -> Result<Json<Motoboy>, ApiError> {
+    let motoboy = _update_motoboy(&mut conn, motoboy_id, motoboy.into_inner()).or_not_found("Motoboy")?;
 
-    let motoboy = _update_motoboy(&mut conn, motoboy_id, motoboy.into_inner());
-
-    match motoboy {
-        Ok(motoboy) => Ok(Json(motoboy)),
-        Err(_) => Err(status::BadRequest(Some("Error updating motoboy".to_string()))),
-    }
+    Ok(Json(motoboy))
 }
 
 fn _update_motoboy(conn: &mut PgConnection, motoboy_id: i32, motoboy: NewMotoboy) -> QueryResult<Motoboy> {
@@ -85,36 +79,80 @@ fn _update_motoboy(conn: &mut PgConnection, motoboy_id: i32, motoboy: NewMotoboy
         .get_result::<Motoboy>(conn)
 }
 
-#[delete("/motoboy/<motoboy_id>")]
-pub fn delete_motoboy(motoboy_id: i32) -> Result<Json<Motoboy>, status::BadRequest<String>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+#[patch("/motoboy/<motoboy_id>", data = "<motoboy>")]
+pub fn patch_motoboy(mut conn: DbConn, motoboy_id: i32, motoboy: Payload<MotoboyChangeset>) -> Result<Json<Motoboy>, ApiError> {
+    let motoboy = _patch_motoboy(&mut conn, motoboy_id, motoboy.into_inner()).or_not_found("Motoboy")?;
 
-    let motoboy = _delete_motoboy(&mut conn, motoboy_id);
+    Ok(Json(motoboy))
+}
+
+fn _patch_motoboy(conn: &mut PgConnection, motoboy_id: i32, motoboy: MotoboyChangeset) -> QueryResult<Motoboy> {
+    let updated = diesel::update(motoboy::table.find(motoboy_id))
+        .set(motoboy)
+        .get_result::<Motoboy>(conn)
+        .optional_empty_changeset()?;
 
-    match motoboy {
-        Ok(motoboy) => Ok(Json(motoboy)),
-        Err(_) => Err(status::BadRequest(Some("Error deleting motoboy".to_string()))),
+    match updated {
+        Some(motoboy) => Ok(motoboy),
+        None => _get_motoboy(conn, motoboy_id),
     }
 }
 
+#[delete("/motoboy/<motoboy_id>")]
+pub fn delete_motoboy(mut conn: DbConn, motoboy_id: i32) -> Result<Json<Motoboy>, ApiError> {
+    let motoboy = _delete_motoboy(&mut conn, motoboy_id).or_not_found("Motoboy")?;
+
+    Ok(Json(motoboy))
+}
+
 fn _delete_motoboy(conn: &mut PgConnection, motoboy_id: i32) -> QueryResult<Motoboy> {
     diesel::delete(motoboy::table.find(motoboy_id))
         .get_result::<Motoboy>(conn)
 }
 
-#[get("/motoboy")]
-pub fn get_motoboys() -> Json<Vec<Motoboy>> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+#[derive(Debug, FromForm)]
+pub struct MotoboyFilter {
+    pub name: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+const MOTOBOY_SORT_FIELDS: &[&str] = &["id", "name", "daily_salary"];
 
-    let motoboys = _get_motoboys(&mut conn);
+#[get("/motoboy?<query..>")]
+pub fn get_motoboys(mut conn: DbConn, query: ListQuery<MotoboyFilter>) -> Result<Json<Page<Motoboy>>, ApiError> {
+    let sort = parse_sort(query.page.sort.as_deref(), MOTOBOY_SORT_FIELDS)?;
+    let motoboys = _get_motoboys(&mut conn, &query.filter, &query.page, &sort)?;
 
-    Json(motoboys)
+    Ok(Json(motoboys))
 }
 
-fn _get_motoboys(conn: &mut PgConnection) -> Vec<Motoboy> {
-    motoboy::table
-        .load::<Motoboy>(conn)
-        .unwrap()
+fn filter_motoboys(filter: &MotoboyFilter) -> motoboy::BoxedQuery<'static, Pg> {
+    let mut query = motoboy::table.into_boxed();
+
+    if let Some(name) = &filter.name {
+        query = query.filter(motoboy::name.ilike(contains_pattern(name)));
+    }
+    if let Some(is_active) = filter.is_active {
+        query = query.filter(motoboy::is_active.eq(is_active));
+    }
+
+    query
+}
+
+fn _get_motoboys(conn: &mut PgConnection, filter: &MotoboyFilter, page: &PageParams, sort: &[SortKey]) -> QueryResult<Page<Motoboy>> {
+    let total = filter_motoboys(filter)
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let motoboys = sort_by!(filter_motoboys(filter), sort, {
+            "id" => motoboy::id,
+            "name" => motoboy::name,
+            "daily_salary" => motoboy::daily_salary,
+        })
+        .then_order_by(motoboy::id)
+        .limit(page.limit)
+        .offset(page.offset)
+        .load::<Motoboy>(conn)?;
+
+    Ok(Page::new(motoboys, total, page))
 }
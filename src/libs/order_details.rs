@@ -1,150 +1,507 @@
-use crate::schema::order_details;
+use crate::schema::{order_details, order_line_component, order_line_modifier};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use diesel::pg::Pg;
 use diesel::prelude::*;
-use diesel::result::Error;
-use rocket::form::{Form, FromForm};
-use rocket::http::Status;
+use rocket::form::FromForm;
 use rocket::serde::json::Json;
-use rocket::serde::Serialize;
-use crate::DATABASE_URL;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+use crate::config::AppConfig;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::money::Money;
+use crate::libs::customer_order::ensure_order_discount_fits;
+use crate::libs::combo::{ComboSelection, ComponentSwap, _resolve_combo};
+use crate::libs::item::{Item, OrderOverrides, _get_orderable_item};
+use crate::libs::modifier::{Selection, _resolve_selection};
+use crate::pagination::{parse_sort, sort_by, ListQuery, Page, PageParams, SortKey};
+use crate::payload::Payload;
 
-#[derive(Debug, Queryable, Serialize)]
+#[derive(Debug, Queryable, Identifiable, Serialize)]
+#[diesel(table_name = order_details)]
 pub struct OrderDetails {
+    pub id: i32,
     pub order_id: i32,
     pub item_id: i32,
+    pub item_name: String,
+    pub quantity: i32,
+    pub unit_price: Money,
+    pub total_price: Money,
+    #[serde(skip)]
+    pub options_key: String,
+}
+
+/// An option chosen on a line, as it was named and priced at order time.
+#[derive(Debug, Queryable, Identifiable, Associations, Serialize)]
+#[diesel(table_name = order_line_modifier, belongs_to(OrderDetails, foreign_key = order_details_id))]
+pub struct OrderLineModifier {
+    pub id: i32,
+    pub order_details_id: i32,
+    pub modifier_option_id: Option<i32>,
+    pub group_name: String,
+    pub option_name: String,
+    pub price_delta: Money,
+}
+
+/// What one unit of a combo line sent to the kitchen, named and priced as
+/// the component item was at order time.
+#[derive(Debug, Queryable, Identifiable, Associations, Serialize)]
+#[diesel(table_name = order_line_component, belongs_to(OrderDetails, foreign_key = order_details_id))]
+pub struct OrderLineComponent {
+    pub id: i32,
+    pub order_details_id: i32,
+    pub component_id: Option<i32>,
+    pub item_id: i32,
+    pub item_name: String,
     pub quantity: i32,
-    pub unit_price: f64,
-    pub total_price: f64,
+    pub unit_price: Money,
 }
 
+/// An order line with its modifiers and combo components, what every line
+/// route returns.
+#[derive(Debug, Serialize)]
+pub struct OrderLine {
+    #[serde(flatten)]
+    pub details: OrderDetails,
+    pub modifiers: Vec<OrderLineModifier>,
+    pub components: Vec<OrderLineComponent>,
+}
 
-#[derive(Debug, AsChangeset, Insertable, FromForm)]
+#[derive(Debug, Insertable)]
 #[diesel(table_name = order_details)]
+struct NewOrderDetailsRow {
+    order_id: i32,
+    item_id: i32,
+    item_name: String,
+    quantity: i32,
+    // item price plus the options' and swaps' deltas, total price is calculated on the database side
+    unit_price: Money,
+    options_key: String,
+}
+
+#[derive(Debug, FromForm, Deserialize)]
 pub struct NewOrderDetails {
     pub order_id: i32,
     pub item_id: i32,
     pub quantity: i32,
-    #[field(default = 0.0)] // later defined on create_order_details
-    pub unit_price: f64, // maybe get from database? no reason to manually input unit price
-    // total price should be calculated automatically on the database side
+    #[serde(default)]
+    pub option_ids: Vec<i32>,
+    #[serde(default)]
+    pub swaps: Vec<ComponentSwap>,
 }
 
-#[get("/order_details/<order_id>")]
-pub fn get_order_details(order_id: i32) -> Result<Json<Vec<OrderDetails>>, (Status, String)> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-
-    let order_details = _get_order_details(&mut conn, order_id);
-
-    match order_details {
-        Ok(order_details) => Ok(Json(order_details)),
-        Err(err) => match err {
-            Error::NotFound => Err((Status::NotFound, "Order details not found".to_string())),
-            Error::DatabaseError(_, info) => Err((Status::InternalServerError, info.message().to_string())),
-            _ => Err((Status::InternalServerError, "Internal server error".to_string())),
+/// Body of the `/order/<order_id>/lines` routes, the order comes from the path.
+#[derive(Debug, FromForm, Deserialize)]
+pub struct NewOrderLine {
+    pub item_id: i32,
+    pub quantity: i32,
+    #[serde(default)]
+    pub option_ids: Vec<i32>,
+    #[serde(default)]
+    pub swaps: Vec<ComponentSwap>,
+}
+
+#[derive(Debug, FromForm, Deserialize)]
+pub struct OrderLineChangeset {
+    pub item_id: Option<i32>,
+    pub quantity: Option<i32>,
+    pub option_ids: Option<Vec<i32>>,
+    pub swaps: Option<Vec<ComponentSwap>>,
+}
+
+/// The options and combo swaps picked for a line, checked against the item.
+#[derive(Debug)]
+pub struct LineChoices {
+    pub selection: Selection,
+    pub combo: ComboSelection,
+}
+
+impl LineChoices {
+    fn unit_price(&self, item: &Item) -> Money {
+        item.price + self.selection.price_delta + self.combo.price_delta
+    }
+
+    fn key(&self) -> String {
+        if self.combo.key.is_empty() {
+            self.selection.key.clone()
+        } else {
+            format!("{}|{}", self.selection.key, self.combo.key)
         }
     }
 }
 
-pub fn _get_order_details(conn: &mut PgConnection, order_id: i32) -> QueryResult<Vec<OrderDetails>> {
-    order_details::table
-        .filter(order_details::order_id.eq(order_id))
-        .load::<OrderDetails>(conn)
-}
-
-#[post("/order_details", data = "<order_details>")]
-pub fn create_order_details(mut order_details: Form<NewOrderDetails>) -> Result<Json<OrderDetails>, (Status, String)> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
-
-    // get unit price from database
-    let unit_price = crate::libs::item::_get_item_price(&mut conn, order_details.item_id);
-
-    // calculate total price
-    match unit_price {
-        Ok(unit_price) => {
-            order_details.unit_price = unit_price;
-
-            let order_details = _create_order_details(&mut conn, order_details.into_inner());
-
-            match order_details {
-                Ok(order_details) => Ok(Json(order_details)),
-                Err(Error::NotFound) => Err((Status::NotFound, "Order details not found".to_string())),
-                Err(Error::DatabaseError(_, information)) => Err((Status::InternalServerError, information.message().to_string())),
-                Err(err) => Err((Status::InternalServerError, err.to_string())),
-            }
-        },
-        // match unit_price errors
-        Err(Error::NotFound) => Err((Status::NotFound, "Item not found".to_string())),
-        Err(Error::DatabaseError(_, information)) => Err((Status::InternalServerError, information.message().to_string())),
-        Err(err) => Err((Status::InternalServerError, err.to_string())),
-    }
+pub fn _resolve_line(conn: &mut PgConnection, item_id: i32, option_ids: &[i32], swaps: &[ComponentSwap], overrides: OrderOverrides, now: &DateTime<Tz>) -> Result<LineChoices, ApiError> {
+    Ok(LineChoices {
+        selection: _resolve_selection(conn, item_id, option_ids)?,
+        combo: _resolve_combo(conn, item_id, swaps, overrides, now)?,
+    })
 }
 
-pub fn _create_order_details(conn: &mut PgConnection, order_details: NewOrderDetails) -> QueryResult<OrderDetails> {
-    diesel::insert_into(order_details::table)
-        .values(order_details)
-        .get_result::<OrderDetails>(conn)
+/// Loads the modifiers and components of `lines`, keeping their order.
+pub fn _with_line_parts(conn: &mut PgConnection, lines: Vec<OrderDetails>) -> QueryResult<Vec<OrderLine>> {
+    let modifiers = OrderLineModifier::belonging_to(&lines)
+        .order(order_line_modifier::id)
+        .load::<OrderLineModifier>(conn)?
+        .grouped_by(&lines);
+    let components = OrderLineComponent::belonging_to(&lines)
+        .order(order_line_component::id)
+        .load::<OrderLineComponent>(conn)?
+        .grouped_by(&lines);
+
+    Ok(lines
+        .into_iter()
+        .zip(modifiers)
+        .zip(components)
+        .map(|((details, modifiers), components)| OrderLine { details, modifiers, components })
+        .collect())
 }
 
-#[get("/order_details")]
-pub fn get_all_order_details() -> Result<Json<Vec<OrderDetails>>, Status> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+fn with_line_parts(conn: &mut PgConnection, line: OrderDetails) -> QueryResult<OrderLine> {
+    let mut lines = _with_line_parts(conn, vec![line])?;
 
-    let order_details = _get_all_order_details(&mut conn);
+    Ok(lines.remove(0))
+}
 
-    match order_details {
-        Ok(order_details) => Ok(Json(order_details)),
-        Err(_) => Err(Status::InternalServerError),
-    }
+#[get("/order_details/<order_id>")]
+pub fn get_order_details(mut conn: DbConn, order_id: i32) -> Result<Json<Vec<OrderLine>>, ApiError> {
+    let order_details = _get_order_details(&mut conn, order_id)?;
+
+    Ok(Json(order_details))
 }
 
-pub fn _get_all_order_details(conn: &mut PgConnection) -> QueryResult<Vec<OrderDetails>> {
-    order_details::table
-        .load::<OrderDetails>(conn)
+pub fn _get_order_details(conn: &mut PgConnection, order_id: i32) -> QueryResult<Vec<OrderLine>> {
+    let lines = order_details::table
+        .filter(order_details::order_id.eq(order_id))
+        .order(order_details::id)
+        .load::<OrderDetails>(conn)?;
+
+    _with_line_parts(conn, lines)
 }
 
-#[put("/order_details/<order_id>", data = "<order_details>")]
-pub fn update_order_details(order_id: i32, order_details: Form<NewOrderDetails>) -> Result<Json<OrderDetails>, (Status, String)> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+#[post("/order_details?<overrides..>", data = "<order_details>")]
+pub fn create_order_details(mut conn: DbConn, config: &State<AppConfig>, overrides: OrderOverrides, order_details: Payload<NewOrderDetails>) -> Result<Json<OrderLine>, ApiError> {
+    let order_details = order_details.into_inner();
+    let now = config.store.now();
+
+    let line = conn.transaction(|conn| {
+        // get unit price and name from database
+        let item = _get_orderable_item(conn, order_details.item_id, overrides, &now)?;
+        let choices = _resolve_line(conn, item.id, &order_details.option_ids, &order_details.swaps, overrides, &now)?;
+
+        _create_order_details(conn, order_details.order_id, &item, order_details.quantity, choices)
+            .map_err(ApiError::from)
+    })?;
+
+    Ok(Json(line))
+}
 
-    let order_details = _update_order_details(&mut conn, order_id, order_details.into_inner());
+/// Adding an item the order already has with the same options and swaps adds
+/// to that line's quantity instead of creating a second line, keeping the
+/// name and prices the line was first ordered at. Must run inside a transaction.
+pub fn _create_order_details(conn: &mut PgConnection, order_id: i32, item: &Item, quantity: i32, choices: LineChoices) -> QueryResult<OrderLine> {
+    let key = choices.key();
+    let line = diesel::insert_into(order_details::table)
+        .values(NewOrderDetailsRow {
+            order_id,
+            item_id: item.id,
+            item_name: item.name.clone(),
+            quantity,
+            unit_price: choices.unit_price(item),
+            options_key: key.clone(),
+        })
+        .on_conflict((order_details::order_id, order_details::item_id, order_details::options_key))
+        .do_nothing()
+        .get_result::<OrderDetails>(conn)
+        .optional()?;
 
-    match order_details {
-        Ok(order_details) => Ok(Json(order_details)),
-        Err(err) => match err {
-            Error::NotFound => Err((Status::NotFound, "Order id not found.".to_string())),
-            err => Err((Status::InternalServerError, err.to_string()))
+    let line = match line {
+        Some(line) => {
+            replace_line_parts(conn, &line, choices)?;
+            line
         }
-    }
+        None => add_to_line(conn, order_id, item.id, &key, quantity)?,
+    };
+
+    with_line_parts(conn, line)
 }
 
-pub fn _update_order_details(conn: &mut PgConnection, order_id: i32, order_details: NewOrderDetails) -> QueryResult<OrderDetails> {
+/// Adds `quantity` to the order's line for the item with these options and swaps.
+fn add_to_line(conn: &mut PgConnection, order_id: i32, item_id: i32, key: &str, quantity: i32) -> QueryResult<OrderDetails> {
     diesel::update(order_details::table)
         .filter(order_details::order_id.eq(order_id))
-        .set(order_details)
+        .filter(order_details::item_id.eq(item_id))
+        .filter(order_details::options_key.eq(key))
+        .set(order_details::quantity.eq(order_details::quantity + quantity))
         .get_result::<OrderDetails>(conn)
 }
 
-#[delete("/order_details/<order_id>")]
-pub fn delete_order_details(order_id: i32) -> Result<Json<OrderDetails>, (Status, String)> {
-    let mut conn = PgConnection::establish(DATABASE_URL)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", DATABASE_URL));
+fn replace_line_parts(conn: &mut PgConnection, line: &OrderDetails, choices: LineChoices) -> QueryResult<()> {
+    diesel::delete(order_line_modifier::table)
+        .filter(order_line_modifier::order_details_id.eq(line.id))
+        .execute(conn)?;
+    diesel::delete(order_line_component::table)
+        .filter(order_line_component::order_details_id.eq(line.id))
+        .execute(conn)?;
 
-    let order_details = _delete_order_details(&mut conn, order_id);
+    let modifiers = choices.selection.options
+        .into_iter()
+        .map(|option| (
+            order_line_modifier::order_details_id.eq(line.id),
+            order_line_modifier::modifier_option_id.eq(option.option_id),
+            order_line_modifier::group_name.eq(option.group_name),
+            order_line_modifier::option_name.eq(option.option_name),
+            order_line_modifier::price_delta.eq(option.price_delta),
+        ))
+        .collect::<Vec<_>>();
 
-    match order_details {
-        Ok(order_details) => Ok(Json(order_details)),
-        Err(err) => match err {
-            Error::NotFound => { Err((Status::NotFound, "Order id not found.".to_string())) }
-            err => { Err((Status::InternalServerError, err.to_string())) }
-        }
+    diesel::insert_into(order_line_modifier::table)
+        .values(modifiers)
+        .execute(conn)?;
+
+    let components = choices.combo.components
+        .into_iter()
+        .map(|component| (
+            order_line_component::order_details_id.eq(line.id),
+            order_line_component::component_id.eq(component.component_id),
+            order_line_component::item_id.eq(component.item_id),
+            order_line_component::item_name.eq(component.item_name),
+            order_line_component::quantity.eq(component.quantity),
+            order_line_component::unit_price.eq(component.unit_price),
+        ))
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(order_line_component::table)
+        .values(components)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+#[derive(Debug, FromForm)]
+pub struct OrderDetailsFilter {
+    pub order_id: Option<i32>,
+    pub item_id: Option<i32>,
+}
+
+const ORDER_DETAILS_SORT_FIELDS: &[&str] = &["id", "order_id", "item_id", "quantity", "total_price"];
+
+#[get("/order_details?<query..>")]
+pub fn get_all_order_details(mut conn: DbConn, query: ListQuery<OrderDetailsFilter>) -> Result<Json<Page<OrderLine>>, ApiError> {
+    let sort = parse_sort(query.page.sort.as_deref(), ORDER_DETAILS_SORT_FIELDS)?;
+    let order_details = _get_all_order_details(&mut conn, &query.filter, &query.page, &sort)?;
+
+    Ok(Json(order_details))
+}
+
+fn filter_order_details(filter: &OrderDetailsFilter) -> order_details::BoxedQuery<'static, Pg> {
+    let mut query = order_details::table.into_boxed();
+
+    if let Some(order_id) = filter.order_id {
+        query = query.filter(order_details::order_id.eq(order_id));
+    }
+    if let Some(item_id) = filter.item_id {
+        query = query.filter(order_details::item_id.eq(item_id));
     }
+
+    query
+}
+
+pub fn _get_all_order_details(conn: &mut PgConnection, filter: &OrderDetailsFilter, page: &PageParams, sort: &[SortKey]) -> QueryResult<Page<OrderLine>> {
+    let total = filter_order_details(filter)
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let order_details = sort_by!(filter_order_details(filter), sort, {
+            "id" => order_details::id,
+            "order_id" => order_details::order_id,
+            "item_id" => order_details::item_id,
+            "quantity" => order_details::quantity,
+            "total_price" => order_details::total_price,
+        })
+        .then_order_by(order_details::id)
+        .limit(page.limit)
+        .offset(page.offset)
+        .load::<OrderDetails>(conn)?;
+
+    Ok(Page::new(_with_line_parts(conn, order_details)?, total, page))
+}
+
+#[get("/order/<order_id>/lines")]
+pub fn get_order_lines(mut conn: DbConn, order_id: i32) -> Result<Json<Vec<OrderLine>>, ApiError> {
+    let lines = _get_order_details(&mut conn, order_id)?;
+
+    Ok(Json(lines))
+}
+
+#[get("/order/<order_id>/lines/<line_id>")]
+pub fn get_order_line(mut conn: DbConn, order_id: i32, line_id: i32) -> Result<Json<OrderLine>, ApiError> {
+    let line = _get_order_line(&mut conn, order_id, line_id).or_not_found("Order line")?;
+
+    Ok(Json(line))
 }
 
-pub fn _delete_order_details(conn: &mut PgConnection, order_id: i32) -> QueryResult<OrderDetails> {
-    diesel::delete(order_details::table)
+pub fn _get_order_line(conn: &mut PgConnection, order_id: i32, line_id: i32) -> QueryResult<OrderLine> {
+    let line = order_details::table
+        .find(line_id)
         .filter(order_details::order_id.eq(order_id))
-        .get_result::<OrderDetails>(conn)
-}
\ No newline at end of file
+        .first::<OrderDetails>(conn)?;
+
+    with_line_parts(conn, line)
+}
+
+#[post("/order/<order_id>/lines?<overrides..>", data = "<line>")]
+pub fn create_order_line(mut conn: DbConn, config: &State<AppConfig>, order_id: i32, overrides: OrderOverrides, line: Payload<NewOrderLine>) -> Result<Json<OrderLine>, ApiError> {
+    let line = line.into_inner();
+    let now = config.store.now();
+
+    let line = conn.transaction(|conn| {
+        let item = _get_orderable_item(conn, line.item_id, overrides, &now)?;
+        let choices = _resolve_line(conn, item.id, &line.option_ids, &line.swaps, overrides, &now)?;
+
+        _create_order_details(conn, order_id, &item, line.quantity, choices)
+            .map_err(ApiError::from)
+    })?;
+
+    Ok(Json(line))
+}
+
+#[put("/order/<order_id>/lines/<line_id>?<overrides..>", data = "<line>")]
+pub fn update_order_line(mut conn: DbConn, config: &State<AppConfig>, order_id: i32, line_id: i32, overrides: OrderOverrides, line: Payload<NewOrderLine>) -> Result<Json<OrderLine>, ApiError> {
+    let line = line.into_inner();
+    let now = config.store.now();
+
+    let line = conn.transaction(|conn| {
+        let item = _get_orderable_item(conn, line.item_id, overrides, &now)?;
+        let choices = _resolve_line(conn, item.id, &line.option_ids, &line.swaps, overrides, &now)?;
+
+        let line = _update_order_line(conn, order_id, line_id, line.quantity, &item, choices)
+            .or_not_found("Order line")?;
+
+        ensure_order_discount_fits(conn, order_id).map(|_| line)
+    })?;
+
+    Ok(Json(line))
+}
+
+/// Replaces a line's item, options, swaps and quantity, taking the item's
+/// current name and price. When that makes it the same as another line of
+/// the order, the line is merged into that one the way adding it again would.
+/// Must run inside a transaction.
+pub fn _update_order_line(conn: &mut PgConnection, order_id: i32, line_id: i32, quantity: i32, item: &Item, choices: LineChoices) -> QueryResult<OrderLine> {
+    let key = choices.key();
+    let same = order_details::table
+        .filter(order_details::order_id.eq(order_id))
+        .filter(order_details::item_id.eq(item.id))
+        .filter(order_details::options_key.eq(&key))
+        .filter(order_details::id.ne(line_id))
+        .select(order_details::id)
+        .for_update()
+        .first::<i32>(conn)
+        .optional()?;
+
+    if same.is_some() {
+        let deleted = diesel::delete(order_details::table.find(line_id))
+            .filter(order_details::order_id.eq(order_id))
+            .execute(conn)?;
+        if deleted == 0 {
+            return Err(diesel::result::Error::NotFound);
+        }
+
+        let line = add_to_line(conn, order_id, item.id, &key, quantity)?;
+
+        return with_line_parts(conn, line);
+    }
+
+    let line = diesel::update(order_details::table.find(line_id))
+        .filter(order_details::order_id.eq(order_id))
+        .set((
+            order_details::item_id.eq(item.id),
+            order_details::item_name.eq(&item.name),
+            order_details::quantity.eq(quantity),
+            order_details::unit_price.eq(choices.unit_price(item)),
+            order_details::options_key.eq(key),
+        ))
+        .get_result::<OrderDetails>(conn)?;
+
+    replace_line_parts(conn, &line, choices)?;
+
+    with_line_parts(conn, line)
+}
+
+#[patch("/order/<order_id>/lines/<line_id>?<overrides..>", data = "<line>")]
+pub fn patch_order_line(mut conn: DbConn, config: &State<AppConfig>, order_id: i32, line_id: i32, overrides: OrderOverrides, line: Payload<OrderLineChangeset>) -> Result<Json<OrderLine>, ApiError> {
+    let line = line.into_inner();
+    let now = config.store.now();
+
+    let line = conn.transaction(|conn| {
+        let line = _patch_order_line(conn, order_id, line_id, line, overrides, &now)?;
+
+        ensure_order_discount_fits(conn, order_id).map(|_| line)
+    })?;
+
+    Ok(Json(line))
+}
+
+/// A new quantity alone keeps the line's name and price. A new item, new
+/// options or new swaps re-price the line like a PUT, and a new item starts
+/// without options or swaps unless `option_ids` or `swaps` are sent too.
+pub fn _patch_order_line(conn: &mut PgConnection, order_id: i32, line_id: i32, line: OrderLineChangeset, overrides: OrderOverrides, now: &DateTime<Tz>) -> Result<OrderLine, ApiError> {
+    let current = _get_order_line(conn, order_id, line_id).or_not_found("Order line")?;
+    let quantity = line.quantity.unwrap_or(current.details.quantity);
+
+    if line.item_id.is_none() && line.option_ids.is_none() && line.swaps.is_none() {
+        let updated = diesel::update(order_details::table.find(line_id))
+            .set(order_details::quantity.eq(quantity))
+            .get_result::<OrderDetails>(conn)?;
+
+        return Ok(with_line_parts(conn, updated)?);
+    }
+
+    let item_id = line.item_id.unwrap_or(current.details.item_id);
+    let option_ids = match line.option_ids {
+        Some(option_ids) => option_ids,
+        None if item_id == current.details.item_id => current.modifiers
+            .iter()
+            .filter_map(|modifier| modifier.modifier_option_id)
+            .collect(),
+        None => Vec::new(),
+    };
+    // components still pointing at their slot keep the item they went out with
+    let swaps = match line.swaps {
+        Some(swaps) => swaps,
+        None if item_id == current.details.item_id => current.components
+            .iter()
+            .filter_map(|component| component.component_id.map(|component_id| ComponentSwap {
+                component_id,
+                item_id: component.item_id,
+            }))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let item = _get_orderable_item(conn, item_id, overrides, now)?;
+    let choices = _resolve_line(conn, item.id, &option_ids, &swaps, overrides, now)?;
+
+    Ok(_update_order_line(conn, order_id, line_id, quantity, &item, choices)?)
+}
+
+#[delete("/order/<order_id>/lines/<line_id>")]
+pub fn delete_order_line(mut conn: DbConn, order_id: i32, line_id: i32) -> Result<Json<OrderLine>, ApiError> {
+    let line = conn.transaction(|conn| {
+        let line = _delete_order_line(conn, order_id, line_id).or_not_found("Order line")?;
+
+        ensure_order_discount_fits(conn, order_id).map(|_| line)
+    })?;
+
+    Ok(Json(line))
+}
+
+pub fn _delete_order_line(conn: &mut PgConnection, order_id: i32, line_id: i32) -> QueryResult<OrderLine> {
+    let line = _get_order_line(conn, order_id, line_id)?;
+
+    diesel::delete(order_details::table.find(line_id))
+        .execute(conn)?;
+
+    Ok(line)
+}
@@ -0,0 +1,257 @@
+use crate::schema::{customer_order, order_status_history};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::SmallInt;
+use rocket::form::{FromForm, FromFormField};
+use rocket::serde::json::{json, Json};
+use rocket::time::OffsetDateTime;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::libs::customer_order::_get_order;
+use crate::libs::inventory::{_consume_order_stock, _restore_order_stock};
+use crate::payload::Payload;
+use serde::{Deserialize, Serialize};
+
+
+/// Lifecycle of an order, stored as a checked smallint in `customer_order.status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, FromFormField, Serialize, Deserialize)]
+#[diesel(sql_type = SmallInt)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    #[field(value = "received")]
+    Received,
+    #[field(value = "preparing")]
+    Preparing,
+    #[field(value = "ready")]
+    Ready,
+    #[field(value = "out_for_delivery")]
+    OutForDelivery,
+    #[field(value = "delivered")]
+    Delivered,
+    #[field(value = "cancelled")]
+    Cancelled,
+}
+
+impl OrderStatus {
+    const ALL: [OrderStatus; 6] = [
+        OrderStatus::Received,
+        OrderStatus::Preparing,
+        OrderStatus::Ready,
+        OrderStatus::OutForDelivery,
+        OrderStatus::Delivered,
+        OrderStatus::Cancelled,
+    ];
+
+    fn to_i16(self) -> i16 {
+        match self {
+            OrderStatus::Received => 0,
+            OrderStatus::Preparing => 1,
+            OrderStatus::Ready => 2,
+            OrderStatus::OutForDelivery => 3,
+            OrderStatus::Delivered => 4,
+            OrderStatus::Cancelled => 5,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrderStatus::Received => "received",
+            OrderStatus::Preparing => "preparing",
+            OrderStatus::Ready => "ready",
+            OrderStatus::OutForDelivery => "out_for_delivery",
+            OrderStatus::Delivered => "delivered",
+            OrderStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Ready orders may go straight to delivered for counter pickups. Delivered
+    /// and cancelled are final.
+    pub fn can_transition_to(self, next: OrderStatus) -> bool {
+        use OrderStatus::*;
+
+        matches!(
+            (self, next),
+            (Received, Preparing) | (Received, Cancelled)
+                | (Preparing, Ready) | (Preparing, Cancelled)
+                | (Ready, OutForDelivery) | (Ready, Delivered) | (Ready, Cancelled)
+                | (OutForDelivery, Delivered) | (OutForDelivery, Cancelled)
+        )
+    }
+
+    pub fn next_statuses(self) -> Vec<OrderStatus> {
+        OrderStatus::ALL.into_iter()
+            .filter(|next| self.can_transition_to(*next))
+            .collect()
+    }
+}
+
+impl ToSql<SmallInt, Pg> for OrderStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <i16 as ToSql<SmallInt, Pg>>::to_sql(&self.to_i16(), &mut out.reborrow())
+    }
+}
+
+impl FromSql<SmallInt, Pg> for OrderStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        let value = <i16 as FromSql<SmallInt, Pg>>::from_sql(bytes)?;
+
+        OrderStatus::ALL.into_iter()
+            .find(|status| status.to_i16() == value)
+            .ok_or_else(|| format!("Unrecognized order status {}", value).into())
+    }
+}
+
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct OrderStatusChange {
+    pub id: i32,
+    pub order_id: i32,
+    pub from_status: OrderStatus,
+    pub to_status: OrderStatus,
+    pub changed_by: String,
+    pub changed_at: OffsetDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = order_status_history)]
+struct NewOrderStatusChange {
+    order_id: i32,
+    from_status: OrderStatus,
+    to_status: OrderStatus,
+    changed_by: String,
+}
+
+#[derive(Debug, FromForm, Deserialize)]
+pub struct Transition {
+    pub status: OrderStatus,
+    pub changed_by: String,
+}
+
+
+#[post("/order/<order_id>/transition", data = "<transition>")]
+pub fn transition_order(mut conn: DbConn, order_id: i32, transition: Payload<Transition>) -> Result<Json<OrderStatusChange>, ApiError> {
+    let transition = transition.into_inner();
+
+    if transition.changed_by.trim().is_empty() {
+        return Err(ApiError::Validation("changed_by must not be empty".to_string(), None));
+    }
+
+    let change = conn.transaction(|conn| _transition_order(conn, order_id, transition))?;
+
+    Ok(Json(change))
+}
+
+/// Moves an order to a new status and records who did it. Starting to prepare
+/// an order takes its ingredients out of stock, cancelling it puts them back.
+/// Must run inside a transaction, the order row stays locked until it commits.
+pub fn _transition_order(conn: &mut PgConnection, order_id: i32, transition: Transition) -> Result<OrderStatusChange, ApiError> {
+    let current = customer_order::table
+        .find(order_id)
+        .select(customer_order::status)
+        .for_update()
+        .first::<OrderStatus>(conn)
+        .or_not_found("Order")?;
+
+    if !current.can_transition_to(transition.status) {
+        return Err(ApiError::Conflict(
+            format!("Cannot move an order from {} to {}", current.as_str(), transition.status.as_str()),
+            Some(json!({
+                "from": current,
+                "to": transition.status,
+                "allowed": current.next_statuses(),
+            })),
+        ));
+    }
+
+    diesel::update(customer_order::table.find(order_id))
+        .set(customer_order::status.eq(transition.status))
+        .execute(conn)?;
+
+    match transition.status {
+        OrderStatus::Preparing => _consume_order_stock(conn, order_id)?,
+        OrderStatus::Cancelled => _restore_order_stock(conn, order_id)?,
+        _ => 0,
+    };
+
+    let change = diesel::insert_into(order_status_history::table)
+        .values(NewOrderStatusChange {
+            order_id,
+            from_status: current,
+            to_status: transition.status,
+            changed_by: transition.changed_by,
+        })
+        .get_result::<OrderStatusChange>(conn)?;
+
+    Ok(change)
+}
+
+#[get("/order/<order_id>/history")]
+pub fn get_order_history(mut conn: DbConn, order_id: i32) -> Result<Json<Vec<OrderStatusChange>>, ApiError> {
+    _get_order(&mut conn, order_id).or_not_found("Order")?;
+    let history = _get_order_history(&mut conn, order_id)?;
+
+    Ok(Json(history))
+}
+
+pub fn _get_order_history(conn: &mut PgConnection, order_id: i32) -> QueryResult<Vec<OrderStatusChange>> {
+    order_status_history::table
+        .filter(order_status_history::order_id.eq(order_id))
+        .order(order_status_history::changed_at)
+        .load::<OrderStatusChange>(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use OrderStatus::*;
+
+    #[test]
+    fn orders_move_forward_one_step() {
+        assert!(Received.can_transition_to(Preparing));
+        assert!(Preparing.can_transition_to(Ready));
+        assert!(Ready.can_transition_to(OutForDelivery));
+        assert!(OutForDelivery.can_transition_to(Delivered));
+    }
+
+    #[test]
+    fn ready_orders_can_be_picked_up() {
+        assert!(Ready.can_transition_to(Delivered));
+    }
+
+    #[test]
+    fn open_orders_can_be_cancelled() {
+        for status in [Received, Preparing, Ready, OutForDelivery] {
+            assert!(status.can_transition_to(Cancelled), "{}", status.as_str());
+        }
+    }
+
+    #[test]
+    fn illegal_transitions_are_refused() {
+        assert!(!Received.can_transition_to(Ready));
+        assert!(!Received.can_transition_to(Delivered));
+        assert!(!Preparing.can_transition_to(Received));
+        assert!(!Preparing.can_transition_to(OutForDelivery));
+        assert!(!OutForDelivery.can_transition_to(Ready));
+    }
+
+    #[test]
+    fn no_status_moves_to_itself() {
+        for status in OrderStatus::ALL {
+            assert!(!status.can_transition_to(status), "{}", status.as_str());
+        }
+    }
+
+    #[test]
+    fn delivered_and_cancelled_are_final() {
+        assert!(Delivered.next_statuses().is_empty());
+        assert!(Cancelled.next_statuses().is_empty());
+    }
+
+    #[test]
+    fn next_statuses_follow_can_transition_to() {
+        assert_eq!(Ready.next_statuses(), vec![OutForDelivery, Delivered, Cancelled]);
+    }
+}
@@ -0,0 +1,206 @@
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Date as SqlDate, Int4, Int8, Nullable, Numeric, SmallInt, Varchar};
+use rocket::form::{FromForm, FromFormField};
+use rocket::serde::json::Json;
+use rocket::time::Date;
+use crate::db::DbConn;
+use crate::error::ApiError;
+use crate::libs::order_status::OrderStatus;
+use crate::money::Money;
+use crate::percent::Percent;
+use serde::Serialize;
+
+
+#[derive(Debug, FromForm)]
+pub struct ReportPeriod {
+    pub from: Option<Date>,
+    pub to: Option<Date>,
+}
+
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct ChannelRevenue {
+    #[diesel(sql_type = Int4)]
+    pub sales_channel_id: i32,
+    #[diesel(sql_type = Varchar)]
+    pub name: String,
+    /// The channel's current rate, orders are charged at the one they were taken at.
+    #[diesel(sql_type = Numeric)]
+    pub commission_percent: Percent,
+    #[diesel(sql_type = Int8)]
+    pub orders: i64,
+    #[diesel(sql_type = Numeric)]
+    pub gross_revenue: Money,
+    #[diesel(sql_type = Numeric)]
+    pub commission: Money,
+    #[diesel(sql_type = Numeric)]
+    pub net_revenue: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevenueReport {
+    pub from: Option<Date>,
+    pub to: Option<Date>,
+    pub orders: i64,
+    pub gross_revenue: Money,
+    pub commission: Money,
+    pub net_revenue: Money,
+    pub channels: Vec<ChannelRevenue>,
+}
+
+impl ReportPeriod {
+    fn check(&self) -> Result<(), ApiError> {
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from > to {
+                return Err(ApiError::BadRequest("`from` must not be after `to`".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[get("/report/revenue?<period..>")]
+pub fn get_revenue_report(mut conn: DbConn, period: ReportPeriod) -> Result<Json<RevenueReport>, ApiError> {
+    period.check()?;
+
+    let channels = _get_channel_revenue(&mut conn, &period)?;
+
+    Ok(Json(RevenueReport {
+        from: period.from,
+        to: period.to,
+        orders: channels.iter().map(|channel| channel.orders).sum(),
+        gross_revenue: channels.iter().map(|channel| channel.gross_revenue).sum(),
+        commission: channels.iter().map(|channel| channel.commission).sum(),
+        net_revenue: channels.iter().map(|channel| channel.net_revenue).sum(),
+        channels,
+    }))
+}
+
+/// Revenue of every sales channel in the period, cancelled orders excluded.
+/// Commission is charged per order on its grand total at the rate snapshotted
+/// on the order, rounded to cents half away from zero.
+pub fn _get_channel_revenue(conn: &mut PgConnection, period: &ReportPeriod) -> QueryResult<Vec<ChannelRevenue>> {
+    diesel::sql_query("
+        WITH order_revenue AS (
+            SELECT o.id, o.sales_channel_id, o.grand_total AS gross,
+                   round(o.grand_total * o.commission_percent / 100, 2) AS commission
+            FROM customer_order o
+            WHERE o.status <> $1
+              AND ($2 IS NULL OR o.date >= $2)
+              AND ($3 IS NULL OR o.date <= $3)
+        )
+        SELECT c.id AS sales_channel_id, c.name, c.commission_percent,
+               count(r.id) AS orders,
+               COALESCE(sum(r.gross), 0) AS gross_revenue,
+               COALESCE(sum(r.commission), 0) AS commission,
+               COALESCE(sum(r.gross - r.commission), 0) AS net_revenue
+        FROM sales_channel c
+        LEFT JOIN order_revenue r ON r.sales_channel_id = c.id
+        GROUP BY c.id
+        ORDER BY c.id
+    ")
+        .bind::<SmallInt, _>(OrderStatus::Cancelled)
+        .bind::<Nullable<SqlDate>, _>(period.from)
+        .bind::<Nullable<SqlDate>, _>(period.to)
+        .load::<ChannelRevenue>(conn)
+}
+
+/// Whom combo sales are credited to in the sales by item report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromFormField, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemCredit {
+    /// The combo item itself, as sold.
+    #[default]
+    Combo,
+    /// The items that went to the kitchen for it.
+    Components,
+}
+
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct ItemSales {
+    #[diesel(sql_type = Int4)]
+    pub item_id: i32,
+    #[diesel(sql_type = Varchar)]
+    pub item_name: String,
+    #[diesel(sql_type = Int8)]
+    pub quantity: i64,
+    #[diesel(sql_type = Numeric)]
+    pub revenue: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ItemSalesReport {
+    pub from: Option<Date>,
+    pub to: Option<Date>,
+    pub credit: ItemCredit,
+    pub quantity: i64,
+    pub revenue: Money,
+    pub items: Vec<ItemSales>,
+}
+
+#[get("/report/items?<credit>&<period..>")]
+pub fn get_item_sales_report(mut conn: DbConn, credit: Option<ItemCredit>, period: ReportPeriod) -> Result<Json<ItemSalesReport>, ApiError> {
+    period.check()?;
+
+    let credit = credit.unwrap_or_default();
+    let items = _get_item_sales(&mut conn, &period, credit)?;
+
+    Ok(Json(ItemSalesReport {
+        from: period.from,
+        to: period.to,
+        credit,
+        quantity: items.iter().map(|item| item.quantity).sum(),
+        revenue: items.iter().map(|item| item.revenue).sum(),
+        items,
+    }))
+}
+
+/// Units sold and line revenue per item in the period, cancelled orders
+/// excluded, best sellers first. Items are named as on their latest line.
+///
+/// Crediting components, a combo line counts as its components times the line
+/// quantity and its revenue is split between them by their list prices at order
+/// time (evenly by quantity when they were all free), rounded to cents per line.
+pub fn _get_item_sales(conn: &mut PgConnection, period: &ReportPeriod, credit: ItemCredit) -> QueryResult<Vec<ItemSales>> {
+    diesel::sql_query("
+        WITH line AS (
+            SELECT d.id, d.item_id, d.item_name, d.quantity, d.total_price
+            FROM order_details d
+            JOIN customer_order o ON o.id = d.order_id
+            WHERE o.status <> $1
+              AND ($2 IS NULL OR o.date >= $2)
+              AND ($3 IS NULL OR o.date <= $3)
+        ),
+        component AS (
+            SELECT c.order_details_id AS line_id, c.item_id, c.item_name,
+                   l.quantity * c.quantity AS quantity,
+                   round(l.total_price * COALESCE(
+                       c.quantity * c.unit_price / NULLIF(sum(c.quantity * c.unit_price) OVER w, 0),
+                       c.quantity::numeric / sum(c.quantity) OVER w
+                   ), 2) AS revenue
+            FROM order_line_component c
+            JOIN line l ON l.id = c.order_details_id
+            WHERE $4
+            WINDOW w AS (PARTITION BY c.order_details_id)
+        ),
+        credited AS (
+            SELECT line_id, item_id, item_name, quantity, revenue FROM component
+            UNION ALL
+            SELECT l.id, l.item_id, l.item_name, l.quantity, l.total_price
+            FROM line l
+            WHERE NOT EXISTS (SELECT 1 FROM component c WHERE c.line_id = l.id)
+        )
+        SELECT item_id,
+               (array_agg(item_name ORDER BY line_id DESC))[1] AS item_name,
+               sum(quantity)::int8 AS quantity,
+               sum(revenue) AS revenue
+        FROM credited
+        GROUP BY item_id
+        ORDER BY revenue DESC, quantity DESC, item_id
+    ")
+        .bind::<SmallInt, _>(OrderStatus::Cancelled)
+        .bind::<Nullable<SqlDate>, _>(period.from)
+        .bind::<Nullable<SqlDate>, _>(period.to)
+        .bind::<Bool, _>(credit == ItemCredit::Components)
+        .load::<ItemSales>(conn)
+}
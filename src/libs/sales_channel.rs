@@ -0,0 +1,171 @@
+use crate::schema::sales_channel;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::result::OptionalEmptyChangesetExtension;
+use rocket::form::FromForm;
+use crate::db::DbConn;
+use crate::error::{ApiError, OrNotFound};
+use crate::pagination::{contains_pattern, parse_sort, sort_by, ListQuery, Page, PageParams, SortKey};
+use crate::payload::Payload;
+use crate::percent::Percent;
+use serde::{Deserialize, Serialize};
+use rocket::serde::json::{json, Json};
+
+
+#[derive(Debug, Queryable, Serialize)]
+pub struct SalesChannel {
+    pub id: i32,
+    pub name: String,
+    pub commission_percent: Percent,
+    pub is_active: bool,
+}
+
+
+#[derive(Debug, AsChangeset, Insertable, FromForm, Deserialize)]
+#[diesel(table_name = sales_channel)]
+pub struct NewSalesChannel {
+    pub name: String,
+    pub commission_percent: Percent,
+    pub is_active: bool,
+}
+
+#[derive(Debug, AsChangeset, FromForm, Deserialize)]
+#[diesel(table_name = sales_channel)]
+pub struct SalesChannelChangeset {
+    pub name: Option<String>,
+    pub commission_percent: Option<Percent>,
+    pub is_active: Option<bool>,
+}
+
+#[get("/sales_channel/<sales_channel_id>")]
+pub fn get_sales_channel(mut conn: DbConn, sales_channel_id: i32) -> Result<Json<SalesChannel>, ApiError> {
+    let sales_channel = _get_sales_channel(&mut conn, sales_channel_id).or_not_found("Sales channel")?;
+
+    Ok(Json(sales_channel))
+}
+
+pub fn _get_sales_channel(conn: &mut PgConnection, sales_channel_id: i32) -> QueryResult<SalesChannel> {
+    sales_channel::table
+        .find(sales_channel_id)
+        .first::<SalesChannel>(conn)
+}
+
+#[post("/sales_channel", data = "<sales_channel>")]
+pub fn create_sales_channel(mut conn: DbConn, sales_channel: Payload<NewSalesChannel>) -> Result<Json<SalesChannel>, ApiError> {
+    let sales_channel = _create_sales_channel(&mut conn, sales_channel.into_inner())?;
+
+    Ok(Json(sales_channel))
+}
+
+fn _create_sales_channel(conn: &mut PgConnection, sales_channel: NewSalesChannel) -> QueryResult<SalesChannel> {
+    diesel::insert_into(sales_channel::table)
+        .values(sales_channel)
+        .get_result::<SalesChannel>(conn)
+}
+
+#[put("/sales_channel/<sales_channel_id>", data = "<sales_channel>")]
+pub fn update_sales_channel(mut conn: DbConn, sales_channel_id: i32, sales_channel: Payload<NewSalesChannel>) -> Result<Json<SalesChannel>, ApiError> {
+    let sales_channel = _update_sales_channel(&mut conn, sales_channel_id, sales_channel.into_inner()).or_not_found("Sales channel")?;
+
+    Ok(Json(sales_channel))
+}
+
+fn _update_sales_channel(conn: &mut PgConnection, sales_channel_id: i32, sales_channel: NewSalesChannel) -> QueryResult<SalesChannel> {
+    diesel::update(sales_channel::table.find(sales_channel_id))
+        .set(sales_channel)
+        .get_result::<SalesChannel>(conn)
+}
+
+#[patch("/sales_channel/<sales_channel_id>", data = "<sales_channel>")]
+pub fn patch_sales_channel(mut conn: DbConn, sales_channel_id: i32, sales_channel: Payload<SalesChannelChangeset>) -> Result<Json<SalesChannel>, ApiError> {
+    let sales_channel = _patch_sales_channel(&mut conn, sales_channel_id, sales_channel.into_inner()).or_not_found("Sales channel")?;
+
+    Ok(Json(sales_channel))
+}
+
+fn _patch_sales_channel(conn: &mut PgConnection, sales_channel_id: i32, sales_channel: SalesChannelChangeset) -> QueryResult<SalesChannel> {
+    let updated = diesel::update(sales_channel::table.find(sales_channel_id))
+        .set(sales_channel)
+        .get_result::<SalesChannel>(conn)
+        .optional_empty_changeset()?;
+
+    match updated {
+        Some(sales_channel) => Ok(sales_channel),
+        None => _get_sales_channel(conn, sales_channel_id),
+    }
+}
+
+#[delete("/sales_channel/<sales_channel_id>")]
+pub fn delete_sales_channel(mut conn: DbConn, sales_channel_id: i32) -> Result<Json<SalesChannel>, ApiError> {
+    let sales_channel = _delete_sales_channel(&mut conn, sales_channel_id).or_not_found("Sales channel")?;
+
+    Ok(Json(sales_channel))
+}
+
+fn _delete_sales_channel(conn: &mut PgConnection, sales_channel_id: i32) -> QueryResult<SalesChannel> {
+    diesel::delete(sales_channel::table.find(sales_channel_id))
+        .get_result::<SalesChannel>(conn)
+}
+
+#[derive(Debug, FromForm)]
+pub struct SalesChannelFilter {
+    pub name: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+const SALES_CHANNEL_SORT_FIELDS: &[&str] = &["id", "name", "commission_percent"];
+
+#[get("/sales_channel?<query..>")]
+pub fn get_sales_channels(mut conn: DbConn, query: ListQuery<SalesChannelFilter>) -> Result<Json<Page<SalesChannel>>, ApiError> {
+    let sort = parse_sort(query.page.sort.as_deref(), SALES_CHANNEL_SORT_FIELDS)?;
+    let sales_channels = _get_sales_channels(&mut conn, &query.filter, &query.page, &sort)?;
+
+    Ok(Json(sales_channels))
+}
+
+fn filter_sales_channels(filter: &SalesChannelFilter) -> sales_channel::BoxedQuery<'static, Pg> {
+    let mut query = sales_channel::table.into_boxed();
+
+    if let Some(name) = &filter.name {
+        query = query.filter(sales_channel::name.ilike(contains_pattern(name)));
+    }
+    if let Some(is_active) = filter.is_active {
+        query = query.filter(sales_channel::is_active.eq(is_active));
+    }
+
+    query
+}
+
+fn _get_sales_channels(conn: &mut PgConnection, filter: &SalesChannelFilter, page: &PageParams, sort: &[SortKey]) -> QueryResult<Page<SalesChannel>> {
+    let total = filter_sales_channels(filter)
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let sales_channels = sort_by!(filter_sales_channels(filter), sort, {
+            "id" => sales_channel::id,
+            "name" => sales_channel::name,
+            "commission_percent" => sales_channel::commission_percent,
+        })
+        .then_order_by(sales_channel::id)
+        .limit(page.limit)
+        .offset(page.offset)
+        .load::<SalesChannel>(conn)?;
+
+    Ok(Page::new(sales_channels, total, page))
+}
+
+/// New orders can only be taken on active channels. Existing orders keep their
+/// channel when it is deactivated later.
+pub fn ensure_active_sales_channel(conn: &mut PgConnection, sales_channel_id: i32) -> Result<(), ApiError> {
+    match _get_sales_channel(conn, sales_channel_id).optional()? {
+        Some(sales_channel) if sales_channel.is_active => Ok(()),
+        Some(sales_channel) => Err(ApiError::Validation(
+            format!("Sales channel {} is not active", sales_channel.name),
+            Some(json!({ "sales_channel_id": sales_channel_id })),
+        )),
+        None => Err(ApiError::Validation(
+            format!("Sales channel {} does not exist", sales_channel_id),
+            Some(json!({ "sales_channel_id": sales_channel_id })),
+        )),
+    }
+}
@@ -0,0 +1,101 @@
+use std::iter::Sum;
+use std::ops::Add;
+
+use crate::decimal::decimal_type;
+
+decimal_type! {
+    /// An amount in the store currency, exact to the cent.
+    ///
+    /// Results of arithmetic are rounded to cents half away from zero, the same
+    /// rule Postgres applies to `round()` and to `NUMERIC(12, 2)` columns. Amounts
+    /// coming from clients must already be in cents and fit the columns.
+    pub struct Money: NUMERIC(12, 2);
+    noun = "amount", expecting = "an amount such as \"12.50\" or 12.5";
+}
+
+impl Money {
+    /// `None` when the sum doesn't fit a `Decimal`, long before it stops
+    /// fitting anything meant to be an amount.
+    pub fn checked_add(self, other: Money) -> Option<Money> {
+        self.0.checked_add(other.0).map(Money::rounded)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        self.checked_add(other).expect("amount overflow")
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::serde::json::serde_json;
+
+    use super::*;
+
+    fn parse(value: &str) -> String {
+        Money::parse(value).unwrap().to_string()
+    }
+
+    #[test]
+    fn parses_whole_and_cent_amounts() {
+        assert_eq!(parse("12"), "12.00");
+        assert_eq!(parse("12.5"), "12.50");
+        assert_eq!(parse(" 0.01 "), "0.01");
+        assert_eq!(parse("1.500"), "1.50");
+    }
+
+    #[test]
+    fn rejects_fractions_of_a_cent() {
+        assert!(Money::parse("0.001").is_err());
+        assert!(Money::parse("1.005").is_err());
+        assert!(Money::parse("-0.125").is_err());
+    }
+
+    #[test]
+    fn keeps_negative_amounts() {
+        assert_eq!(parse("-3.5"), "-3.50");
+        assert!(Money::parse("-3.50").unwrap() < Money::ZERO);
+    }
+
+    #[test]
+    fn rejects_amounts_the_columns_cant_hold() {
+        assert_eq!(parse("9999999999.99"), "9999999999.99");
+        assert_eq!(parse("-9999999999.99"), "-9999999999.99");
+        assert!(Money::parse("10000000000").is_err());
+        assert!(Money::parse("-10000000000.00").is_err());
+        assert!(Money::parse("99999999999999999999999999999").is_err());
+    }
+
+    #[test]
+    fn rejects_anything_else() {
+        for value in ["", "abc", "1,50", "1.2.3", "NaN"] {
+            assert!(Money::parse(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn deserializes_strings_and_numbers() {
+        let amounts: Vec<Money> = serde_json::from_str(r#"["0.10", 0.1, 3, -2]"#).unwrap();
+
+        assert_eq!(amounts.iter().map(Money::to_string).collect::<Vec<_>>(), ["0.10", "0.10", "3.00", "-2.00"]);
+        assert!(serde_json::from_str::<Money>("0.001").is_err());
+        assert!(serde_json::from_str::<Money>("1e11").is_err());
+    }
+
+    #[test]
+    fn checked_add_reports_overflow() {
+        let max = Money(rust_decimal::Decimal::MAX);
+
+        assert_eq!(Money::parse("0.10").unwrap().checked_add(Money::parse("0.20").unwrap()), Some(Money::parse("0.30").unwrap()));
+        assert_eq!(max.checked_add(max), None);
+    }
+}
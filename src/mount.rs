@@ -1,22 +1,130 @@
+use std::error::Error;
+use std::time::Duration;
+
+use rocket::fairing::AdHoc;
+use rocket::{Build, Ignite, Rocket};
+use crate::config::{self, AppConfig};
+use crate::db::{self, DbPool};
+use crate::error;
 use crate::libs::*;
 use address::*;
 use neighborhood::*;
 use customer::*;
+use customer_address::*;
+use customer_merge::*;
+use customer_history::*;
 use customer_order::*;
 use item::*;
 use motoboy::*;
 use order_details::*;
+use order_status::*;
+use sales_channel::*;
+use report::*;
+use full_order::*;
+use item_category::*;
+use menu::*;
+use modifier::*;
+use combo::*;
+use inventory::*;
+use availability::*;
+use item_price::*;
+
+pub fn rocket() -> Rocket<Build> {
+    rocket::custom(config::figment())
+        .attach(AdHoc::try_on_ignite("Application config", |rocket| async {
+            match AppConfig::from_figment(rocket.figment()) {
+                Ok(config) => Ok(rocket.manage(db::init_pool(&config.database)).manage(config)),
+                Err(err) => {
+                    rocket::config::pretty_print_error(*err);
+                    Err(rocket)
+                }
+            }
+        }))
+        .attach(AdHoc::try_on_ignite("Database migrations", |rocket| async {
+            let pool = match rocket.state::<DbPool>() {
+                Some(pool) => pool.clone(),
+                None => return Ok(rocket),
+            };
+
+            match rocket::tokio::task::spawn_blocking(move || db::run_migrations(&pool)).await {
+                Ok(Ok(versions)) => {
+                    for version in versions {
+                        info!("Applied migration {}", version);
+                    }
+                    Ok(rocket)
+                }
+                Ok(Err(err)) => {
+                    error!("Failed to run database migrations: {}", err);
+                    Err(rocket)
+                }
+                Err(err) => {
+                    error!("Failed to run database migrations: {}", err);
+                    Err(rocket)
+                }
+            }
+        }))
+        .attach(AdHoc::on_liftoff("Scheduled prices", |rocket| Box::pin(async move {
+            let pool = match rocket.state::<DbPool>() {
+                Some(pool) => pool.clone(),
+                None => return,
+            };
 
-#[launch]
-pub fn rocket() -> _ {
-    rocket::build()
+            // item.price follows scheduled price changes within a minute, order
+            // lines read the price history directly and are always exact
+            rocket::tokio::spawn(async move {
+                let mut interval = rocket::tokio::time::interval(Duration::from_secs(60));
+                loop {
+                    interval.tick().await;
+
+                    let pool = pool.clone();
+                    match rocket::tokio::task::spawn_blocking(move || apply_due_prices(&pool)).await {
+                        Ok(Ok(0)) => {}
+                        Ok(Ok(count)) => info!("Applied scheduled prices to {} item(s)", count),
+                        Ok(Err(err)) => error!("Failed to apply scheduled prices: {}", err),
+                        Err(err) => error!("Failed to apply scheduled prices: {}", err),
+                    }
+                }
+            });
+        })))
         .mount("/", routes![
-            get_address, create_address, get_addresses, update_address, delete_address,
-            get_customer, create_customer, get_customers, update_customer, delete_customer,
-            get_order, get_orders, create_order, update_order, delete_order,
-            get_item, create_item, get_all_items, update_item, delete_item,
-            get_motoboy, create_motoboy, get_motoboys, update_motoboy, delete_motoboy,
-            get_neighborhood, create_neighborhood, get_neighborhoods, update_neighborhood, delete_neighborhood,
-            get_order_details, create_order_details, get_all_order_details, update_order_details, delete_order_details
+            get_address, create_address, get_addresses, update_address, patch_address, delete_address,
+            get_customer, get_customer_by_phone, create_customer, get_customers, search_customers, update_customer, patch_customer, delete_customer,
+            get_duplicate_customers, merge_customers, get_customer_merges,
+            get_customer_orders, get_customer_stats,
+            get_customer_addresses, add_customer_address, set_default_customer_address, delete_customer_address,
+            get_order, get_orders, create_order, update_order, patch_order, delete_order,
+            transition_order, get_order_history, get_full_order, create_full_order,
+            get_item, create_item, get_all_items, update_item, patch_item, delete_item,
+            get_item_category, create_item_category, get_item_categories, update_item_category, patch_item_category, delete_item_category,
+            get_modifier_group, get_modifier_groups, create_modifier_group, update_modifier_group, patch_modifier_group, delete_modifier_group,
+            get_modifier_options, create_modifier_option, get_modifier_option, update_modifier_option, patch_modifier_option, delete_modifier_option,
+            get_item_modifier_groups, attach_item_modifier_group, detach_item_modifier_group,
+            get_item_components, create_item_component, update_item_component, delete_item_component,
+            create_component_swap, delete_component_swap,
+            get_ingredient, create_ingredient, get_ingredients, update_ingredient, patch_ingredient, delete_ingredient,
+            get_low_stock_ingredients, create_stock_movement, get_stock_movements,
+            get_item_recipe, set_item_recipe_ingredient, delete_item_recipe_ingredient,
+            get_item_availability_windows, create_item_availability_window,
+            get_category_availability_windows, create_category_availability_window,
+            update_availability_window, delete_availability_window,
+            get_item_prices, schedule_item_price, delete_scheduled_item_price,
+            get_menu,
+            get_motoboy, create_motoboy, get_motoboys, update_motoboy, patch_motoboy, delete_motoboy,
+            get_neighborhood, create_neighborhood, get_neighborhoods, update_neighborhood, patch_neighborhood, delete_neighborhood,
+            get_order_details, create_order_details, get_all_order_details,
+            get_order_lines, get_order_line, create_order_line, update_order_line, patch_order_line, delete_order_line,
+            get_sales_channel, create_sales_channel, get_sales_channels, update_sales_channel, patch_sales_channel, delete_sales_channel,
+            get_revenue_report, get_item_sales_report
         ])
-}
\ No newline at end of file
+        .register("/", catchers![error::default_catcher])
+}
+/// Normalizes the phones stored before they were, for `--normalize-phones`.
+/// Run once after upgrading, with the server stopped.
+pub async fn normalize_phones(rocket: &Rocket<Ignite>) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let (pool, config) = match (rocket.state::<DbPool>(), rocket.state::<AppConfig>()) {
+        (Some(pool), Some(config)) => (pool.clone(), config.store.phone.clone()),
+        _ => return Err("the application config is not loaded".into()),
+    };
+
+    rocket::tokio::task::spawn_blocking(move || normalize_stored_phones(&pool, &config)).await?
+}
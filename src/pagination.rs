@@ -0,0 +1,197 @@
+use rocket::form::{self, DataField, FromForm, Options, ValueField};
+use serde::Serialize;
+
+use crate::error::ApiError;
+
+const DEFAULT_LIMIT: i64 = 50;
+
+#[derive(Debug, FromForm)]
+pub struct PageParams {
+    #[field(default = DEFAULT_LIMIT, validate = range(1..=500))]
+    pub limit: i64,
+    #[field(default = 0, validate = range(0..))]
+    pub offset: i64,
+    pub sort: Option<String>,
+}
+
+/// Query string of a list endpoint: `limit`, `offset` and `sort` go to `page`,
+/// every other field to the resource's own filter struct.
+///
+/// Rocket only allows one trailing `<param..>` per route, hence the manual impl.
+#[derive(Debug)]
+pub struct ListQuery<F> {
+    pub page: PageParams,
+    pub filter: F,
+}
+
+#[rocket::async_trait]
+impl<'v, F: FromForm<'v>> FromForm<'v> for ListQuery<F> {
+    type Context = (<PageParams as FromForm<'v>>::Context, F::Context);
+
+    fn init(opts: Options) -> Self::Context {
+        (PageParams::init(opts), F::init(opts))
+    }
+
+    fn push_value(ctxt: &mut Self::Context, field: ValueField<'v>) {
+        match field.name.key_lossy().as_str() {
+            "limit" | "offset" | "sort" => PageParams::push_value(&mut ctxt.0, field),
+            _ => F::push_value(&mut ctxt.1, field),
+        }
+    }
+
+    async fn push_data(ctxt: &mut Self::Context, field: DataField<'v, '_>) {
+        F::push_data(&mut ctxt.1, field).await
+    }
+
+    fn finalize(ctxt: Self::Context) -> form::Result<'v, Self> {
+        match (PageParams::finalize(ctxt.0), F::finalize(ctxt.1)) {
+            (Ok(page), Ok(filter)) => Ok(ListQuery { page, filter }),
+            (Err(mut errors), Err(filter_errors)) => {
+                errors.extend(filter_errors);
+                Err(errors)
+            }
+            (Err(errors), _) | (_, Err(errors)) => Err(errors),
+        }
+    }
+}
+
+/// One page of a list endpoint, with the total number of matching rows.
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: i64, page: &PageParams) -> Page<T> {
+        Page { items, total, limit: page.limit, offset: page.offset }
+    }
+}
+
+#[derive(Debug)]
+pub struct SortKey {
+    pub field: String,
+    pub descending: bool,
+}
+
+/// Parses `?sort=field,-field` against the fields a resource allows sorting by.
+/// A field can only be sorted by once.
+pub fn parse_sort(sort: Option<&str>, allowed: &[&str]) -> Result<Vec<SortKey>, ApiError> {
+    let sort = match sort {
+        Some(sort) => sort,
+        None => return Ok(Vec::new()),
+    };
+
+    let keys = sort.split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            let (field, descending) = match field.strip_prefix('-') {
+                Some(field) => (field, true),
+                None => (field, false),
+            };
+
+            if allowed.contains(&field) {
+                Ok(SortKey { field: field.to_string(), descending })
+            } else {
+                Err(ApiError::BadRequest(format!("Cannot sort by `{}`, expected one of: {}", field, allowed.join(", "))))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (index, key) in keys.iter().enumerate() {
+        if keys[..index].iter().any(|other| other.field == key.field) {
+            return Err(ApiError::BadRequest(format!("Cannot sort by `{}` more than once", key.field)));
+        }
+    }
+
+    Ok(keys)
+}
+
+/// `ILIKE` pattern matching `term` anywhere, with the wildcards in `term` escaped.
+pub fn contains_pattern(term: &str) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    format!("%{}%", escaped)
+}
+
+/// Applies parsed sort keys to a boxed query, mapping field names to columns:
+///
+/// ```ignore
+/// let query = sort_by!(query, sort, { "id" => item::id, "name" => item::name });
+/// ```
+///
+/// Field names must already be validated by `parse_sort`.
+macro_rules! sort_by {
+    ($query:expr, $keys:expr, { $($field:literal => $column:expr),+ $(,)? }) => {{
+        let mut query = $query;
+        for key in $keys.iter() {
+            query = match key.field.as_str() {
+                $($field => if key.descending {
+                    query.then_order_by($column.desc())
+                } else {
+                    query.then_order_by($column.asc())
+                },)+
+                _ => unreachable!("sort key validated by parse_sort"),
+            };
+        }
+        query
+    }};
+}
+
+pub(crate) use sort_by;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[&str] = &["id", "name", "price"];
+
+    fn keys(sort: &str) -> Vec<(String, bool)> {
+        parse_sort(Some(sort), FIELDS).unwrap()
+            .into_iter()
+            .map(|key| (key.field, key.descending))
+            .collect()
+    }
+
+    fn error(sort: &str) -> String {
+        parse_sort(Some(sort), FIELDS).unwrap_err().message().to_string()
+    }
+
+    #[test]
+    fn no_sort_means_no_keys() {
+        assert!(parse_sort(None, FIELDS).unwrap().is_empty());
+        assert!(parse_sort(Some(""), FIELDS).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parses_keys_in_order() {
+        assert_eq!(keys("name,-price"), [("name".to_string(), false), ("price".to_string(), true)]);
+        assert_eq!(keys(" -id , name ,"), [("id".to_string(), true), ("name".to_string(), false)]);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert_eq!(error("colour"), "Cannot sort by `colour`, expected one of: id, name, price");
+        assert!(error("name,-colour").contains("`colour`"));
+        assert!(error("--name").contains("`-name`"));
+        assert!(error("Name").contains("`Name`"));
+    }
+
+    #[test]
+    fn rejects_duplicate_keys() {
+        assert_eq!(error("name,name"), "Cannot sort by `name` more than once");
+        assert_eq!(error("id,-price,-id"), "Cannot sort by `id` more than once");
+    }
+
+    #[test]
+    fn rejected_keys_are_bad_requests() {
+        assert!(matches!(parse_sort(Some("colour"), FIELDS), Err(ApiError::BadRequest(_))));
+        assert!(matches!(parse_sort(Some("id,id"), FIELDS), Err(ApiError::BadRequest(_))));
+    }
+}
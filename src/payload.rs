@@ -0,0 +1,101 @@
+use std::ops::{Deref, DerefMut};
+
+use rocket::data::{self, Data, FromData};
+use rocket::form::{self, Form, FromForm};
+use rocket::outcome::Outcome;
+use rocket::request::Request;
+use rocket::serde::json::{self, json, Json};
+use serde::{Deserialize, Deserializer};
+
+use crate::error::ApiError;
+
+/// Request body accepted as either `application/json` or form data.
+///
+/// Picks the parser from the request's Content-Type, so one route serves both
+/// the POS frontend and plain HTML forms. Anything else is rejected with 415.
+#[derive(Debug)]
+pub struct Payload<T>(pub T);
+
+impl<T> Payload<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Payload<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Payload<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// Serde helper for `Option<Option<T>>` changeset fields, used together with
+/// `#[serde(default)]`: a missing key stays `None` (column untouched) while an
+/// explicit `null` becomes `Some(None)` and clears the column.
+pub fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+    where T: Deserialize<'de>, D: Deserializer<'de>
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+fn form_error(errors: form::Errors<'_>) -> ApiError {
+    let details = errors.iter()
+        .map(|error| json!({
+            "field": error.name.as_ref().map(|name| name.to_string()),
+            "message": error.kind.to_string(),
+        }))
+        .collect::<Vec<_>>();
+
+    ApiError::Validation("The form data is invalid".to_string(), Some(json!(details)))
+}
+
+fn json_error(error: json::Error<'_>) -> ApiError {
+    match error {
+        json::Error::Parse(_, err) if err.is_syntax() || err.is_eof() => ApiError::BadRequest(format!("Malformed JSON: {}", err)),
+        json::Error::Parse(_, err) => ApiError::Validation(format!("Invalid JSON body: {}", err), None),
+        json::Error::Io(err) => ApiError::BadRequest(format!("Could not read the request body: {}", err)),
+    }
+}
+
+fn fail<'r, T>(request: &'r Request<'_>, error: ApiError) -> data::Outcome<'r, T, ApiError> {
+    Outcome::Error((error.status(), error.stash(request)))
+}
+
+#[rocket::async_trait]
+impl<'r, T> FromData<'r> for Payload<T>
+    where T: Deserialize<'r> + FromForm<'r> + Send
+{
+    type Error = ApiError;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let content_type = request.content_type();
+
+        if content_type.is_some_and(|content_type| content_type.is_json()) {
+            match Json::<T>::from_data(request, data).await {
+                Outcome::Success(body) => Outcome::Success(Payload(body.into_inner())),
+                Outcome::Error((_, err)) => fail(request, json_error(err)),
+                Outcome::Forward(forward) => Outcome::Forward(forward),
+            }
+        } else if content_type.is_some_and(|content_type| content_type.is_form() || content_type.is_form_data()) {
+            match Form::<T>::from_data(request, data).await {
+                Outcome::Success(form) => Outcome::Success(Payload(form.into_inner())),
+                Outcome::Error((_, errors)) => fail(request, form_error(errors)),
+                Outcome::Forward(forward) => Outcome::Forward(forward),
+            }
+        } else {
+            let message = match content_type {
+                Some(content_type) => format!("Unsupported content type {}, send JSON or form data", content_type),
+                None => "Missing Content-Type, send JSON or form data".to_string(),
+            };
+
+            fail(request, ApiError::UnsupportedMediaType(message))
+        }
+    }
+}
@@ -0,0 +1,11 @@
+use crate::decimal::decimal_type;
+
+decimal_type! {
+    /// A rate from 0 to 100 percent, exact to the hundredth, such as a sales
+    /// channel's commission.
+    ///
+    /// Amounts taken at a rate are rounded by whoever applies it, see
+    /// `report::_get_channel_revenue`.
+    pub struct Percent: NUMERIC(5, 2);
+    noun = "percentage", expecting = "a percentage such as \"12.50\" or 12.5", range = 0..=100;
+}
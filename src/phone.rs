@@ -0,0 +1,123 @@
+use crate::config::PhoneConfig;
+
+/// Most digits an E.164 number can have, country code included.
+const MAX_DIGITS: usize = 15;
+/// Fewest digits accepted once the defaults are filled in.
+const MIN_DIGITS: usize = 8;
+
+/// Whether `phone` is already in the canonical `+<digits>` form.
+pub fn is_canonical(phone: &str) -> bool {
+    phone.strip_prefix('+')
+        .is_some_and(|digits| (MIN_DIGITS..=MAX_DIGITS).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Brings a phone number as typed at the counter (`"(11) 99999-0000"`,
+/// `"011 99999 0000"`, `"+55 11 99999-0000"`) to the canonical E.164 form
+/// `"+5511999990000"`.
+///
+/// Numbers starting with `+` already carry their country code. Otherwise
+/// national trunk zeros are dropped, and the configured area and country codes
+/// are added when the number is too short to contain them.
+pub fn normalize(value: &str, config: &PhoneConfig) -> Result<String, String> {
+    let value = value.trim();
+
+    if let Some(c) = value.chars().find(|c| !c.is_ascii_digit() && !" +-().".contains(*c)) {
+        return Err(format!("`{}` is not a phone number, `{}` is not allowed", value, c));
+    }
+    if value.rfind('+').is_some_and(|position| position > 0) {
+        return Err(format!("`{}` is not a phone number, `+` can only come first", value));
+    }
+
+    let mut digits = value.chars().filter(char::is_ascii_digit).collect::<String>();
+
+    if !value.starts_with('+') {
+        digits = digits.trim_start_matches('0').to_string();
+
+        if digits.len() < config.min_local_digits {
+            return Err(format!("`{}` is too short for a phone number", value));
+        }
+        if digits.len() <= config.max_local_digits {
+            match &config.area_code {
+                Some(area_code) => digits.insert_str(0, area_code),
+                None => return Err(format!("`{}` needs an area code", value)),
+            }
+        }
+        if digits.len() <= config.area_code_digits + config.max_local_digits {
+            digits.insert_str(0, &config.country_code);
+        }
+    }
+
+    if !(MIN_DIGITS..=MAX_DIGITS).contains(&digits.len()) {
+        return Err(format!("`{}` is not a phone number, it has too few or too many digits", value));
+    }
+
+    Ok(format!("+{}", digits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> PhoneConfig {
+        PhoneConfig { area_code: Some("11".to_string()), ..PhoneConfig::default() }
+    }
+
+    #[test]
+    fn normalizes_numbers_as_typed() {
+        assert_eq!(normalize("(11) 99999-0000", &store()).unwrap(), "+5511999990000");
+        assert_eq!(normalize("+55 11 99999-0000", &store()).unwrap(), "+5511999990000");
+        assert_eq!(normalize(" 55 11 3333 4444 ", &store()).unwrap(), "+551133334444");
+    }
+
+    #[test]
+    fn fills_in_the_store_area_code() {
+        assert_eq!(normalize("99999-0000", &store()).unwrap(), "+5511999990000");
+        assert_eq!(normalize("3333-4444", &store()).unwrap(), "+551133334444");
+    }
+
+    #[test]
+    fn drops_trunk_zeros() {
+        assert_eq!(normalize("011 99999 0000", &store()).unwrap(), "+5511999990000");
+        assert_eq!(normalize("0 21 3333-4444", &store()).unwrap(), "+552133334444");
+    }
+
+    #[test]
+    fn keeps_foreign_numbers_as_sent() {
+        assert_eq!(normalize("+1 (212) 555-0100", &store()).unwrap(), "+12125550100");
+    }
+
+    #[test]
+    fn rejects_a_plus_anywhere_but_first() {
+        assert!(normalize("55+11999990000", &store()).unwrap_err().contains("`+` can only come first"));
+        assert!(normalize("++5511999990000", &store()).is_err());
+    }
+
+    #[test]
+    fn rejects_letters() {
+        assert!(normalize("11 9999-CASA", &store()).unwrap_err().contains("`C` is not allowed"));
+    }
+
+    #[test]
+    fn needs_an_area_code_when_the_store_has_none() {
+        let config = PhoneConfig::default();
+
+        assert!(normalize("99999-0000", &config).unwrap_err().contains("needs an area code"));
+        assert_eq!(normalize("(11) 99999-0000", &config).unwrap(), "+5511999990000");
+    }
+
+    #[test]
+    fn rejects_too_short_and_too_long_numbers() {
+        assert!(normalize("999-000", &store()).unwrap_err().contains("too short"));
+        assert!(normalize("000 9999", &store()).unwrap_err().contains("too short"));
+        assert!(normalize("+1234567", &store()).is_err());
+        assert!(normalize("+1234567890123456", &store()).is_err());
+    }
+
+    #[test]
+    fn canonical_phones_are_recognized() {
+        assert!(is_canonical("+5511999990000"));
+        assert!(!is_canonical("5511999990000"));
+        assert!(!is_canonical("+55 11 99999-0000"));
+        assert!(is_canonical(&normalize("(11) 99999-0000", &store()).unwrap()));
+    }
+}
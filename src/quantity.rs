@@ -0,0 +1,18 @@
+use std::ops::Neg;
+
+use crate::decimal::decimal_type;
+
+decimal_type! {
+    /// An amount of an ingredient in its own unit (grams, ml, units, ...), exact to
+    /// the thousandth.
+    pub struct Quantity: NUMERIC(12, 3);
+    noun = "quantity", expecting = "a quantity such as \"1.250\" or 1.25";
+}
+
+impl Neg for Quantity {
+    type Output = Quantity;
+
+    fn neg(self) -> Quantity {
+        Quantity(-self.0)
+    }
+}